@@ -1,79 +1,81 @@
 /// Our example plugin for Virtual Pinball
 mod fpscounter;
 
-use log::info;
-use std::cell::RefCell;
-use std::rc::Rc;
+use log::{info, warn};
 
-use vpinball_plugin_api::{
-    plugin, Plugin, VPXApi, VPXPI_EVT_ON_GAME_END, VPXPI_EVT_ON_GAME_START,
-    VPXPI_EVT_ON_PREPARE_FRAME, VPXPI_EVT_ON_SETTINGS_CHANGED, VPXPI_NAMESPACE,
-};
+use vpinball_plugin_api::{plugin, GameSession, GameState, Plugin, TableInfo, VPXApi};
+
+/// Counts the frames of a single game, so the first second of a table is not mixed with the
+/// previous one.
+struct FpsGame {
+    fps_counter: fpscounter::FPSCounter,
+}
+
+impl GameState for FpsGame {
+    fn start(_api: &dyn VPXApi, _table: &TableInfo) -> Self {
+        Self {
+            fps_counter: fpscounter::FPSCounter::new(),
+        }
+    }
+
+    fn prepare_frame(&mut self, _api: &dyn VPXApi) {
+        if let Some(fps) = self.fps_counter.update() {
+            info!("FPS: {:.2}", fps);
+        }
+    }
+}
 
 struct FpsPlugin {
-    fps_counter: Rc<RefCell<fpscounter::FPSCounter>>,
+    session: Option<GameSession<FpsGame>>,
 }
 
 impl Plugin for FpsPlugin {
+    const ID: &'static str = "fps";
     fn new() -> Self {
-        Self {
-            fps_counter: Rc::new(RefCell::new(fpscounter::FPSCounter::new())),
-        }
+        Self { session: None }
     }
 
     fn on_load(&mut self, vpx: &mut dyn VPXApi) {
         info!("Plugin loading");
-        let fps_counter_clone = Rc::clone(&self.fps_counter);
-        // TODO on the example this is the session_id that is passed on plugin
-        vpx.subscribe_msg(
-            VPXPI_NAMESPACE,
-            VPXPI_EVT_ON_GAME_START,
-            Box::new(|event_id| {
-                info!("plugin event {event_id}: Game is starting");
-                // Game is starting (plugin can be loaded and kept alive through multiple game plays)
-                // After this event, all functions of the API marked as 'in game only' can be called
-
-                let plugin = get_plugin_api();
-
-                let setup = plugin.get_active_view_setup();
-                info!("Active view setup:");
-                info!("  View mode: {:?}", setup.viewMode);
-
-                let table = plugin.get_table_info();
-                info!("Active table: {}", table.path);
-
-                plugin.push_notification("Hello World", 5000);
-            }),
-        );
-        vpx.subscribe_msg(
-            VPXPI_NAMESPACE,
-            VPXPI_EVT_ON_GAME_END,
-            Box::new(|event_id| {
-                info!("plugin event {event_id}: Game is ending");
-            }),
-        );
-        vpx.subscribe_msg(
-            VPXPI_NAMESPACE,
-            VPXPI_EVT_ON_PREPARE_FRAME,
-            Box::new(move |_event_id| {
-                let mut fps_counter = fps_counter_clone.borrow_mut();
-                let fps = fps_counter.update();
-                if let Some(fps) = fps {
-                    info!("FPS: {:.2}", fps);
-                }
-            }),
-        );
-        vpx.subscribe_msg(
-            VPXPI_NAMESPACE,
-            VPXPI_EVT_ON_SETTINGS_CHANGED,
-            Box::new(|_event_id| {
-                info!("Settings changed");
-            }),
-        );
+        match vpx.game_session::<FpsGame>() {
+            Ok(session) => self.session = Some(session),
+            Err(error) => warn!("Failed to track games: {error}"),
+        }
     }
 
     fn on_unload(&mut self) {
         info!("Plugin unloading");
+        self.session = None;
+    }
+
+    fn on_game_start(&mut self, api: &dyn VPXApi) {
+        info!("plugin event: Game is starting");
+        // Game is starting (plugin can be loaded and kept alive through multiple game plays)
+        // After this event, all functions of the API marked as 'in game only' can be called
+
+        match api.get_active_view_setup() {
+            Ok(setup) => {
+                info!("Active view setup:");
+                info!("  View mode: {:?}", setup.view_mode);
+            }
+            Err(error) => warn!("No active view setup: {error}"),
+        }
+
+        if let Some(table) = self.session.as_ref().and_then(|session| session.table()) {
+            info!("Active table: {}", table.path);
+        }
+
+        if let Err(error) = api.push_notification("Hello World", 5000) {
+            warn!("Failed to show notification: {error}");
+        }
+    }
+
+    fn on_game_end(&mut self, _api: &dyn VPXApi) {
+        info!("plugin event: Game is ending");
+    }
+
+    fn on_settings_changed(&mut self, _api: &dyn VPXApi) {
+        info!("Settings changed");
     }
 }
 
@@ -94,5 +96,11 @@ mod tests {
         PluginLoad(session_id, &mut api);
 
         PluginUnload();
+
+        // the host may load the same library again, a second load replaces the running instance
+        PluginLoad(session_id, &mut api);
+        PluginLoad(session_id, &mut api);
+        PluginUnload();
+        PluginUnload();
     }
 }
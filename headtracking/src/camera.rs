@@ -0,0 +1,100 @@
+use crate::opentrack::Pose;
+use vpinball_plugin_api::{ViewSetup, VPU_PER_CM};
+
+/// How the tracked head moves the camera.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct Limits {
+    /// Largest offset from the original position in VPX units, horizontal, vertical and depth.
+    pub max_offset: [f32; 3],
+    /// Part of the remaining distance that is kept each frame, 0 follows the head directly.
+    pub smoothing: f32,
+}
+
+/// Moves the view of one game away from where the table put it, following the head.
+pub(crate) struct HeadCamera {
+    original: ViewSetup,
+    target: Pose,
+    /// Smoothed offset in VPX units.
+    offset: [f32; 3],
+    applied: Option<ViewSetup>,
+}
+
+impl HeadCamera {
+    pub fn new(original: ViewSetup) -> Self {
+        Self {
+            original,
+            target: Pose::default(),
+            offset: [0.0; 3],
+            applied: None,
+        }
+    }
+
+    /// The setup the game started with, to put back when it ends.
+    pub fn original(&self) -> &ViewSetup {
+        &self.original
+    }
+
+    pub fn track(&mut self, pose: Pose) {
+        self.target = pose;
+    }
+
+    /// Moves one frame towards the target, returns the setup to apply if it changed.
+    pub fn update(&mut self, limits: &Limits) -> Option<ViewSetup> {
+        let target = [self.target.x, self.target.y, self.target.z].map(|cm| cm * VPU_PER_CM);
+        let follow = 1.0 - limits.smoothing.clamp(0.0, 1.0);
+        for ((offset, target), max) in self.offset.iter_mut().zip(target).zip(limits.max_offset) {
+            let max = max.max(0.0);
+            *offset += (target.clamp(-max, max) - *offset) * follow;
+        }
+        // OpenTrack y is up and z away from the screen, in VPX z is up and y towards the player
+        let [x, y, z] = self.offset;
+        let mut setup = self.original;
+        setup.view_x += x;
+        setup.view_y += z;
+        setup.view_z += y;
+        if self.applied == Some(setup) {
+            return None;
+        }
+        self.applied = Some(setup);
+        Some(setup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smooth_and_clamp() {
+        let original = ViewSetup {
+            view_x: 100.0,
+            ..ViewSetup::default()
+        };
+        let mut camera = HeadCamera::new(original);
+        let limits = Limits {
+            max_offset: [10.0 * VPU_PER_CM; 3],
+            smoothing: 0.5,
+        };
+        assert_eq!(camera.update(&limits), Some(original));
+        assert_eq!(camera.update(&limits), None);
+
+        camera.track(Pose {
+            x: 4.0,
+            y: -50.0,
+            z: 0.0,
+        });
+        let setup = camera.update(&limits).unwrap();
+        assert_eq!(setup.view_x, 100.0 + 2.0 * VPU_PER_CM);
+        // clamped to 10 cm down, halfway there
+        assert_eq!(setup.view_z, -5.0 * VPU_PER_CM);
+
+        let direct = Limits {
+            smoothing: 0.0,
+            ..limits
+        };
+        let setup = camera.update(&direct).unwrap();
+        assert_eq!(setup.view_x, 100.0 + 4.0 * VPU_PER_CM);
+        assert_eq!(setup.view_z, -10.0 * VPU_PER_CM);
+        assert_eq!(camera.original(), &original);
+    }
+}
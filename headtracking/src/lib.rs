@@ -0,0 +1,256 @@
+/// Head tracking for the window view mode, driven by the OpenTrack "UDP over network" output
+mod camera;
+mod opentrack;
+
+use log::{info, warn};
+
+use camera::{HeadCamera, Limits};
+use opentrack::PoseReceiver;
+use vpinball_plugin_api::{
+    plugin, HostFunction, Plugin, PluginOptions, StaticPrerenderGuard, VPXApi, ViewMode, VPU_PER_CM,
+};
+
+#[derive(PluginOptions)]
+#[options(page = "headtracking")]
+struct HeadTrackingOptions {
+    #[option(
+        name = "OpenTrack UDP port",
+        default = 4242,
+        min = 1024,
+        max = 65535,
+        show = "ui"
+    )]
+    port: i32,
+    #[option(
+        name = "Smoothing",
+        default = 50.0,
+        min = 0.0,
+        max = 95.0,
+        step = 5.0,
+        unit = "percent"
+    )]
+    smoothing: f32,
+    #[option(
+        name = "Max horizontal offset (cm)",
+        unit = "distance",
+        default = 15.0 * VPU_PER_CM,
+        min = 0.0,
+        max = 50.0 * VPU_PER_CM,
+        step = VPU_PER_CM
+    )]
+    max_horizontal: f32,
+    #[option(
+        name = "Max vertical offset (cm)",
+        unit = "distance",
+        default = 10.0 * VPU_PER_CM,
+        min = 0.0,
+        max = 50.0 * VPU_PER_CM,
+        step = VPU_PER_CM
+    )]
+    max_vertical: f32,
+    #[option(
+        name = "Max depth offset (cm)",
+        unit = "distance",
+        default = 20.0 * VPU_PER_CM,
+        min = 0.0,
+        max = 50.0 * VPU_PER_CM,
+        step = VPU_PER_CM
+    )]
+    max_depth: f32,
+}
+
+impl HeadTrackingOptions {
+    fn limits(&self) -> Limits {
+        Limits {
+            max_offset: [self.max_horizontal, self.max_vertical, self.max_depth],
+            smoothing: self.smoothing / 100.0,
+        }
+    }
+}
+
+struct HeadTrackingPlugin {
+    /// `None` if the options could not be read, tracking stays off then.
+    options: Option<HeadTrackingOptions>,
+    receiver: Option<PoseReceiver>,
+    camera: Option<HeadCamera>,
+    /// The prerendered parts would not follow the moving view.
+    prerender: Option<StaticPrerenderGuard>,
+}
+
+impl HeadTrackingPlugin {
+    fn bind(&mut self) {
+        let Some(options) = &self.options else {
+            return;
+        };
+        let port = options.port;
+        self.receiver = u16::try_from(port)
+            .map_err(|_| format!("invalid port {port}"))
+            .and_then(|port| PoseReceiver::bind(port).map_err(|error| error.to_string()))
+            .inspect(|receiver| info!("Listening for OpenTrack poses on port {}", receiver.port()))
+            .inspect_err(|error| warn!("Head tracking disabled: {error}"))
+            .ok();
+    }
+
+    fn load_options(&mut self, api: &dyn VPXApi) {
+        self.options = HeadTrackingOptions::load(api)
+            .inspect_err(|error| warn!("Head tracking disabled, options unavailable: {error}"))
+            .ok();
+    }
+}
+
+impl Plugin for HeadTrackingPlugin {
+    const ID: &'static str = "headtracking";
+    const REQUIRED_FUNCTIONS: &'static [HostFunction] = &[
+        HostFunction::GetActiveViewSetup,
+        HostFunction::SetActiveViewSetup,
+    ];
+
+    fn new() -> Self {
+        Self {
+            options: None,
+            receiver: None,
+            camera: None,
+            prerender: None,
+        }
+    }
+
+    fn on_load(&mut self, api: &mut dyn VPXApi) {
+        info!("Head tracking plugin loading");
+        self.load_options(api);
+    }
+
+    fn on_unload(&mut self) {
+        info!("Head tracking plugin unloading");
+        self.receiver = None;
+        self.camera = None;
+        self.prerender = None;
+    }
+
+    fn on_game_start(&mut self, api: &dyn VPXApi) {
+        if self.options.is_none() {
+            return;
+        }
+        let setup = match api.get_active_view_setup() {
+            Ok(setup) => setup,
+            Err(error) => {
+                warn!("Head tracking disabled, no view setup: {error}");
+                return;
+            }
+        };
+        if setup.view_mode != ViewMode::Window {
+            info!("Head tracking works best with the window view mode");
+        }
+        self.camera = Some(HeadCamera::new(setup));
+        self.prerender = api
+            .disable_static_prerendering()
+            .inspect_err(|error| warn!("Static prerendering stays enabled: {error}"))
+            .ok();
+        self.bind();
+    }
+
+    fn on_game_end(&mut self, api: &dyn VPXApi) {
+        self.receiver = None;
+        if let Some(camera) = self.camera.take() {
+            if let Err(error) = api.set_active_view_setup(camera.original()) {
+                warn!("Failed to restore the view: {error}");
+            }
+        }
+        self.prerender = None;
+    }
+
+    fn on_prepare_frame(&mut self, api: &dyn VPXApi) {
+        let (Some(options), Some(receiver), Some(camera)) =
+            (&self.options, &self.receiver, &mut self.camera)
+        else {
+            return;
+        };
+        if let Some(pose) = receiver.latest() {
+            camera.track(pose);
+        }
+        if let Some(setup) = camera.update(&options.limits()) {
+            if let Err(error) = api.set_active_view_setup(&setup) {
+                warn!("Failed to move the view: {error}");
+            }
+        }
+    }
+
+    fn on_settings_changed(&mut self, api: &dyn VPXApi) {
+        let old_port = self.options.as_ref().map(|options| options.port);
+        self.load_options(api);
+        let new_port = self.options.as_ref().map(|options| options.port);
+        if old_port != new_port && self.receiver.is_some() {
+            self.bind();
+        }
+    }
+}
+
+plugin!(HeadTrackingPlugin);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, UdpSocket};
+    use vpinball_plugin_api::bindings::VPXViewSetupDef;
+    use vpinball_plugin_api::messages::{GameEnd, GameStart, PrepareFrame};
+    use vpinball_plugin_api::test::TEST_SESSION_ID;
+    use vpinball_plugin_api::test::{TestMsgPluginAPI, TestVPXPluginAPI};
+    use vpinball_plugin_api::{Message, ViewSetup, VpxError};
+
+    #[test]
+    fn test_track_during_game() {
+        let port = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        TestVPXPluginAPI::set_option("headtracking", "port", port as f32);
+        TestVPXPluginAPI::set_option("headtracking", "smoothing", 0.0);
+        let mut vpx_api = TestVPXPluginAPI::init();
+        TestVPXPluginAPI::enable_view_setup(&mut vpx_api);
+        TestVPXPluginAPI::enable_static_prerendering(&mut vpx_api);
+        let mut msg_api = TestMsgPluginAPI::init(&vpx_api);
+        PluginLoad(TEST_SESSION_ID, &mut msg_api);
+
+        let api = get_plugin_api_handle().unwrap();
+        let broadcast = |namespace: &'static str, name: &'static str| {
+            // the plugin subscribed to the message, so the id outlives the handle
+            let msg_id = api
+                .call(move |api| api.get_msg_id(namespace, name).map(|id| id.raw()))
+                .unwrap()
+                .unwrap();
+            TestMsgPluginAPI::broadcast(msg_id);
+        };
+        broadcast(GameStart::NAMESPACE, GameStart::NAME);
+
+        let sender = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        sender
+            .send_to(
+                &opentrack::packet(5.0, 0.0, 0.0),
+                (Ipv4Addr::LOCALHOST, port),
+            )
+            .unwrap();
+        broadcast(PrepareFrame::NAMESPACE, PrepareFrame::NAME);
+        let view = api.call(|api| api.get_active_view_setup()).unwrap();
+        assert!(view.unwrap().view_x > 0.0);
+        assert_eq!(TestVPXPluginAPI::static_prerendering_calls(), vec![true]);
+
+        broadcast(GameEnd::NAMESPACE, GameEnd::NAME);
+        assert_eq!(
+            TestVPXPluginAPI::static_prerendering_calls(),
+            vec![true, false]
+        );
+        PluginUnload();
+        assert!(get_plugin_api_handle().is_none());
+        assert_eq!(api.call(|_| ()), Err(VpxError::Unloaded));
+
+        // the plugin has no api anymore, so check what the host was left with
+        let mut vpx_api = TestVPXPluginAPI::init();
+        TestVPXPluginAPI::enable_view_setup(&mut vpx_api);
+        let mut view = VPXViewSetupDef::from(&ViewSetup {
+            view_x: 1.0,
+            ..ViewSetup::default()
+        });
+        unsafe { vpx_api.GetActiveViewSetup.unwrap()(&mut view) };
+        assert_eq!(ViewSetup::from(&view), ViewSetup::default());
+    }
+}
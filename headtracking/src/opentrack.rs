@@ -0,0 +1,110 @@
+use log::warn;
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, UdpSocket};
+
+/// Size of an OpenTrack "UDP over network" packet: x, y, z, yaw, pitch and roll as little
+/// endian f64.
+const PACKET_SIZE: usize = 6 * 8;
+
+/// Head position in centimetres as sent by OpenTrack, x to the right, y up and z away from
+/// the screen. The rotation in the packet is not used.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub(crate) struct Pose {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Pose {
+    pub fn parse(packet: &[u8]) -> Option<Pose> {
+        if packet.len() != PACKET_SIZE {
+            return None;
+        }
+        let value = |index: usize| {
+            let bytes = packet[index * 8..(index + 1) * 8].try_into().unwrap();
+            f64::from_le_bytes(bytes) as f32
+        };
+        let pose = Pose {
+            x: value(0),
+            y: value(1),
+            z: value(2),
+        };
+        [pose.x, pose.y, pose.z]
+            .iter()
+            .all(|v| v.is_finite())
+            .then_some(pose)
+    }
+}
+
+/// Receives poses on a local UDP port without blocking the frame.
+pub(crate) struct PoseReceiver {
+    socket: UdpSocket,
+}
+
+impl PoseReceiver {
+    pub fn bind(port: u16) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, port))?;
+        socket.set_nonblocking(true)?;
+        Ok(Self { socket })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.socket.local_addr().map(|a| a.port()).unwrap_or(0)
+    }
+
+    /// Drains everything that arrived since the last call, only the newest pose matters.
+    pub fn latest(&self) -> Option<Pose> {
+        let mut buf = [0u8; 64];
+        let mut latest = None;
+        loop {
+            match self.socket.recv(&mut buf) {
+                Ok(len) => match Pose::parse(&buf[..len]) {
+                    Some(pose) => latest = Some(pose),
+                    None => warn!("Ignoring {len} byte packet, not an OpenTrack pose"),
+                },
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) => {
+                    warn!("Failed to receive pose: {error}");
+                    break;
+                }
+            }
+        }
+        latest
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn packet(x: f64, y: f64, z: f64) -> Vec<u8> {
+    [x, y, z, 10.0, 20.0, 30.0]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_receive_latest_pose() {
+        let receiver = PoseReceiver::bind(0).unwrap();
+        assert_eq!(receiver.latest(), None);
+
+        let sender = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let target = (Ipv4Addr::LOCALHOST, receiver.port());
+        sender.send_to(&packet(1.0, 2.0, 3.0), target).unwrap();
+        sender.send_to(&[1, 2, 3], target).unwrap();
+        sender.send_to(&packet(-4.0, 5.5, 6.0), target).unwrap();
+        assert_eq!(
+            receiver.latest(),
+            Some(Pose {
+                x: -4.0,
+                y: 5.5,
+                z: 6.0
+            })
+        );
+        assert_eq!(receiver.latest(), None);
+
+        assert_eq!(Pose::parse(&packet(f64::NAN, 0.0, 0.0)), None);
+    }
+}
@@ -0,0 +1,76 @@
+//! Derive macros for `vpinball-plugin-api`, use them through the re-exports of that crate.
+
+mod options;
+mod script;
+
+use proc_macro::TokenStream;
+use syn::{parse_macro_input, DeriveInput, ItemImpl};
+
+/// Implements `PluginOptions` for a struct with named fields, see the `options` module of
+/// `vpinball-plugin-api`.
+///
+/// Struct attribute: `#[options(page = "...")]`, the page is the plugin id.
+///
+/// Field attributes, all optional, in `#[option(...)]`:
+/// * `id = "..."` defaults to the field name
+/// * `name = "..."` label shown to the user, defaults to the field name
+/// * `default = <expr>` defaults to `Default::default()`
+/// * `min = <expr>`, `max = <expr>`, `step = <expr>` required for numeric fields
+/// * `unit = "none" | "percent" | "distance"`, `min`, `max`, `step` and `default` are in the
+///   unit of the field, VPX units for a distance
+/// * `show = "all" | "ui" | "tweak"` where the option is shown, defaults to `"all"`
+#[proc_macro_derive(PluginOptions, attributes(options, option))]
+pub fn derive_plugin_options(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    options::derive_plugin_options(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implements `OptionChoice` and `OptionValue` for an enum with unit variants.
+///
+/// Variants are labeled with their name unless they have an `#[option(label = "...")]`.
+#[proc_macro_derive(OptionChoice, attributes(option))]
+pub fn derive_option_choice(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    options::derive_option_choice(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Implements `Scriptable` for a struct so table scripts can create it, the struct needs
+/// `Default` and a `#[script_methods]` impl block, which can be empty.
+///
+/// Struct attribute: `#[script(name = "...")]` the class name, defaults to the struct name.
+///
+/// Fields marked with `#[script]` are properties, named like the field in PascalCase:
+/// * `name = "..."` overrides the property name
+/// * `get`, `set` to only expose one side, both by default
+#[proc_macro_derive(ScriptClass, attributes(script))]
+pub fn derive_script_class(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    script::derive_script_class(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Exposes every method of an impl block to scripts, named like the method in PascalCase or
+/// `#[script(name = "...")]`.
+///
+/// Methods take `&self` or `&mut self` and arguments that implement `ScriptData`. They return
+/// a `ScriptData` or a `Result` of one, errors are raised in the script.
+#[proc_macro_attribute]
+pub fn script_methods(attr: TokenStream, input: TokenStream) -> TokenStream {
+    if !attr.is_empty() {
+        return syn::Error::new(
+            proc_macro2::Span::call_site(),
+            "#[script_methods] takes no arguments",
+        )
+        .into_compile_error()
+        .into();
+    }
+    let input = parse_macro_input!(input as ItemImpl);
+    script::script_methods(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -0,0 +1,248 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput, Expr, Fields, LitStr, Type};
+
+#[derive(Default)]
+struct FieldOptions {
+    id: Option<LitStr>,
+    name: Option<LitStr>,
+    default: Option<Expr>,
+    min: Option<Expr>,
+    max: Option<Expr>,
+    step: Option<Expr>,
+    unit: Option<LitStr>,
+    show: Option<LitStr>,
+}
+
+pub(crate) fn derive_plugin_options(input: DeriveInput) -> syn::Result<TokenStream> {
+    let ident = &input.ident;
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            ident,
+            "PluginOptions can only be derived for structs",
+        ));
+    };
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new_spanned(
+            ident,
+            "PluginOptions requires named fields",
+        ));
+    };
+
+    let mut page: Option<LitStr> = None;
+    for attr in &input.attrs {
+        if attr.path().is_ident("options") {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("page") {
+                    page = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("unknown options attribute, expected `page`"))
+                }
+            })?;
+        }
+    }
+    let Some(page) = page else {
+        return Err(syn::Error::new_spanned(
+            ident,
+            "missing #[options(page = \"...\")], the page is the plugin id",
+        ));
+    };
+
+    let mut reads = Vec::new();
+    for field in &fields.named {
+        let field_ident = field.ident.as_ref().unwrap();
+        let ty = &field.ty;
+        let options = parse_field_options(field)?;
+
+        let field_name = LitStr::new(&field_ident.to_string(), field_ident.span());
+        let id = options.id.unwrap_or_else(|| field_name.clone());
+        let name = options.name.unwrap_or(field_name);
+        if is_numeric(ty) && (options.min.is_none() || options.max.is_none()) {
+            return Err(syn::Error::new_spanned(
+                field,
+                "numeric options need `min` and `max`",
+            ));
+        }
+        let default = match options.default {
+            Some(default) => quote!(#default),
+            None => quote!(::core::default::Default::default()),
+        };
+        let min = optional(options.min);
+        let max = optional(options.max);
+        let step = optional(options.step);
+        let unit = match options.unit.as_ref().map(LitStr::value).as_deref() {
+            None | Some("none") => quote!(::vpinball_plugin_api::bindings::OptionUnit::None),
+            Some("percent") => quote!(::vpinball_plugin_api::bindings::OptionUnit::Percent),
+            Some("distance") => quote!(::vpinball_plugin_api::bindings::OptionUnit::Distance),
+            Some(_) => {
+                return Err(syn::Error::new_spanned(
+                    options.unit,
+                    "unknown unit, expected \"none\", \"percent\" or \"distance\"",
+                ))
+            }
+        };
+        let show_mask = match options.show.as_ref().map(LitStr::value).as_deref() {
+            None | Some("all") => quote!(::vpinball_plugin_api::options::SHOW_ALL),
+            Some("ui") => quote!(::vpinball_plugin_api::bindings::VPX_OPT_SHOW_UI),
+            Some("tweak") => quote!(::vpinball_plugin_api::bindings::VPX_OPT_SHOW_TWEAK),
+            Some(_) => {
+                return Err(syn::Error::new_spanned(
+                    options.show,
+                    "unknown show value, expected \"all\", \"ui\" or \"tweak\"",
+                ))
+            }
+        };
+
+        reads.push(quote! {
+            #field_ident: <#ty as ::vpinball_plugin_api::options::OptionValue>::read(
+                api,
+                &::vpinball_plugin_api::options::OptionDef {
+                    page_id: Self::PAGE_ID,
+                    id: #id,
+                    name: #name,
+                    show_mask: #show_mask,
+                    default: #default,
+                    min: #min,
+                    max: #max,
+                    step: #step,
+                    unit: #unit,
+                },
+            )?,
+        });
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::vpinball_plugin_api::options::PluginOptions for #ident #ty_generics
+            #where_clause
+        {
+            const PAGE_ID: &'static str = #page;
+
+            fn load(
+                api: &dyn ::vpinball_plugin_api::VPXApi,
+            ) -> ::core::result::Result<Self, ::vpinball_plugin_api::VpxError> {
+                ::core::result::Result::Ok(Self {
+                    #(#reads)*
+                })
+            }
+        }
+    })
+}
+
+pub(crate) fn derive_option_choice(input: DeriveInput) -> syn::Result<TokenStream> {
+    let ident = &input.ident;
+    let Data::Enum(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            ident,
+            "OptionChoice can only be derived for enums",
+        ));
+    };
+
+    let mut labels = Vec::new();
+    let mut from_index = Vec::new();
+    let mut to_index = Vec::new();
+    for (index, variant) in data.variants.iter().enumerate() {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(syn::Error::new_spanned(
+                variant,
+                "OptionChoice variants can not have fields",
+            ));
+        }
+        let mut label = LitStr::new(&variant.ident.to_string(), variant.ident.span());
+        for attr in &variant.attrs {
+            if attr.path().is_ident("option") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("label") {
+                        label = meta.value()?.parse()?;
+                        Ok(())
+                    } else {
+                        Err(meta.error("unknown option attribute, expected `label`"))
+                    }
+                })?;
+            }
+        }
+        let variant_ident = &variant.ident;
+        labels.push(label);
+        from_index.push(quote!(#index => ::core::option::Option::Some(Self::#variant_ident),));
+        to_index.push(quote!(Self::#variant_ident => #index,));
+    }
+
+    Ok(quote! {
+        impl ::vpinball_plugin_api::options::OptionChoice for #ident {
+            const LABELS: &'static [&'static str] = &[#(#labels),*];
+
+            fn from_index(index: usize) -> ::core::option::Option<Self> {
+                match index {
+                    #(#from_index)*
+                    _ => ::core::option::Option::None,
+                }
+            }
+
+            fn index(self) -> usize {
+                match self {
+                    #(#to_index)*
+                }
+            }
+        }
+
+        impl ::vpinball_plugin_api::options::OptionValue for #ident {
+            fn read(
+                api: &dyn ::vpinball_plugin_api::VPXApi,
+                option: &::vpinball_plugin_api::options::OptionDef<'_, Self>,
+            ) -> ::core::result::Result<Self, ::vpinball_plugin_api::VpxError> {
+                ::vpinball_plugin_api::options::read_choice(api, option)
+            }
+        }
+    })
+}
+
+fn parse_field_options(field: &syn::Field) -> syn::Result<FieldOptions> {
+    let mut options = FieldOptions::default();
+    for attr in &field.attrs {
+        if !attr.path().is_ident("option") {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            let path = &meta.path;
+            if path.is_ident("id") {
+                options.id = Some(meta.value()?.parse()?);
+            } else if path.is_ident("name") {
+                options.name = Some(meta.value()?.parse()?);
+            } else if path.is_ident("default") {
+                options.default = Some(meta.value()?.parse()?);
+            } else if path.is_ident("min") {
+                options.min = Some(meta.value()?.parse()?);
+            } else if path.is_ident("max") {
+                options.max = Some(meta.value()?.parse()?);
+            } else if path.is_ident("step") {
+                options.step = Some(meta.value()?.parse()?);
+            } else if path.is_ident("unit") {
+                options.unit = Some(meta.value()?.parse()?);
+            } else if path.is_ident("show") {
+                options.show = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("unknown option attribute"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(options)
+}
+
+fn optional(expr: Option<Expr>) -> TokenStream {
+    match expr {
+        Some(expr) => quote!(::core::option::Option::Some(#expr)),
+        None => quote!(::core::option::Option::None),
+    }
+}
+
+fn is_numeric(ty: &Type) -> bool {
+    match ty {
+        Type::Path(path) => path
+            .path
+            .get_ident()
+            .is_some_and(|ident| ident == "f32" || ident == "i32"),
+        _ => false,
+    }
+}
@@ -0,0 +1,243 @@
+use proc_macro2::{Literal, TokenStream};
+use quote::{format_ident, quote};
+use std::ffi::CString;
+use syn::{
+    Attribute, Data, DeriveInput, Fields, FnArg, ImplItem, ItemImpl, LitStr, ReturnType, Type,
+};
+
+#[derive(Default)]
+struct ScriptAttr {
+    name: Option<LitStr>,
+    get: bool,
+    set: bool,
+}
+
+pub(crate) fn derive_script_class(input: DeriveInput) -> syn::Result<TokenStream> {
+    let ident = &input.ident;
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new_spanned(
+            ident,
+            "ScriptClass can only be derived for structs",
+        ));
+    };
+    let class = parse_script_attrs(&input.attrs, false)?;
+    let class_name = class
+        .name
+        .unwrap_or_else(|| LitStr::new(&ident.to_string(), ident.span()));
+    let Ok(class_c_name) = CString::new(class_name.value()) else {
+        return Err(syn::Error::new_spanned(
+            class_name,
+            "script class names can not contain NUL",
+        ));
+    };
+    let class_c_name = Literal::c_string(&class_c_name);
+
+    let mut properties = Vec::new();
+    if let Fields::Named(fields) = &data.fields {
+        for field in &fields.named {
+            if !field
+                .attrs
+                .iter()
+                .any(|attr| attr.path().is_ident("script"))
+            {
+                continue;
+            }
+            let field_ident = field.ident.as_ref().unwrap();
+            let ty = &field.ty;
+            let property = parse_script_attrs(&field.attrs, true)?;
+            let name = property
+                .name
+                .unwrap_or_else(|| LitStr::new(&pascal_case(field_ident), field_ident.span()));
+            // a bare #[script] is a read and write property
+            let (get, set) = match (property.get, property.set) {
+                (false, false) => (true, true),
+                flags => flags,
+            };
+            if get {
+                properties.push(quote! {
+                    .getter(
+                        #name,
+                        <#ty as ::vpinball_plugin_api::ScriptData>::TYPE,
+                        |object| ::vpinball_plugin_api::ScriptData::into_script(
+                            ::core::clone::Clone::clone(&object.#field_ident),
+                        ),
+                    )
+                });
+            }
+            if set {
+                properties.push(quote! {
+                    .try_setter(
+                        #name,
+                        <#ty as ::vpinball_plugin_api::ScriptData>::TYPE,
+                        |object, value| {
+                            object.#field_ident =
+                                <#ty as ::vpinball_plugin_api::ScriptData>::from_script(value)
+                                    .ok_or_else(|| ::std::format!(
+                                        "{} must be {:?}",
+                                        #name,
+                                        <#ty as ::vpinball_plugin_api::ScriptData>::TYPE,
+                                    ))?;
+                            ::core::result::Result::Ok(())
+                        },
+                    )
+                });
+            }
+        }
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::vpinball_plugin_api::Scriptable for #ident #ty_generics
+            #where_clause
+        {
+            const CLASS_NAME: &'static ::core::ffi::CStr = #class_c_name;
+
+            fn script_class() -> ::vpinball_plugin_api::ScriptClass<Self> {
+                let class = ::vpinball_plugin_api::ScriptClass::new(
+                    #class_name,
+                    <Self as ::core::default::Default>::default,
+                )
+                #(#properties)*;
+                <Self as ::vpinball_plugin_api::ScriptMethods>::script_methods(class)
+            }
+        }
+    })
+}
+
+pub(crate) fn script_methods(mut item: ItemImpl) -> syn::Result<TokenStream> {
+    if let Some((_, path, _)) = &item.trait_ {
+        return Err(syn::Error::new_spanned(
+            path,
+            "#[script_methods] goes on an inherent impl block",
+        ));
+    }
+
+    let mut methods = Vec::new();
+    for impl_item in &mut item.items {
+        let ImplItem::Fn(function) = impl_item else {
+            continue;
+        };
+        let method = parse_script_attrs(&function.attrs, false)?;
+        // only the derive declares the attribute, the compiler would reject it here
+        function
+            .attrs
+            .retain(|attr| !attr.path().is_ident("script"));
+        let signature = &function.sig;
+        let method_ident = &signature.ident;
+        let name = method
+            .name
+            .unwrap_or_else(|| LitStr::new(&pascal_case(method_ident), method_ident.span()));
+
+        let mut arg_types = Vec::new();
+        for input in &signature.inputs {
+            match input {
+                FnArg::Receiver(receiver) if receiver.reference.is_some() => {}
+                FnArg::Receiver(receiver) => {
+                    return Err(syn::Error::new_spanned(
+                        receiver,
+                        "script methods take `&self` or `&mut self`",
+                    ))
+                }
+                FnArg::Typed(arg) => arg_types.push(arg.ty.as_ref().clone()),
+            }
+        }
+        if signature.receiver().is_none() {
+            return Err(syn::Error::new_spanned(
+                signature,
+                "script methods take `&self` or `&mut self`, move other functions to another impl block",
+            ));
+        }
+        let ret: Type = match &signature.output {
+            ReturnType::Default => syn::parse_quote!(()),
+            ReturnType::Type(_, ty) => ty.as_ref().clone(),
+        };
+
+        let args = arg_types.iter().enumerate().map(|(index, ty)| {
+            let position = index + 1;
+            quote! {
+                <#ty as ::vpinball_plugin_api::ScriptData>::from_script(
+                    ::core::clone::Clone::clone(&args[#index]),
+                )
+                .ok_or_else(|| ::std::format!(
+                    "argument {} must be {:?}",
+                    #position,
+                    <#ty as ::vpinball_plugin_api::ScriptData>::TYPE,
+                ))?
+            }
+        });
+        let arg_names = (0..arg_types.len()).map(|index| format_ident!("arg{index}"));
+        let arg_names_call = arg_names.clone();
+        methods.push(quote! {
+            .try_method(
+                #name,
+                &[#(<#arg_types as ::vpinball_plugin_api::ScriptData>::TYPE),*],
+                <#ret as ::vpinball_plugin_api::ScriptReturn>::TYPE,
+                |object: &mut Self, args: &[::vpinball_plugin_api::ScriptValue]|
+                    -> ::core::result::Result<::vpinball_plugin_api::ScriptValue, ::std::string::String>
+                {
+                    #(let #arg_names = #args;)*
+                    ::vpinball_plugin_api::ScriptReturn::into_script_result(
+                        object.#method_ident(#(#arg_names_call),*),
+                    )
+                },
+            )
+        });
+    }
+
+    let self_ty = &item.self_ty;
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+    Ok(quote! {
+        #item
+
+        impl #impl_generics ::vpinball_plugin_api::ScriptMethods for #self_ty #where_clause {
+            fn script_methods(
+                class: ::vpinball_plugin_api::ScriptClass<Self>,
+            ) -> ::vpinball_plugin_api::ScriptClass<Self> {
+                class
+                #(#methods)*
+            }
+        }
+    })
+}
+
+fn parse_script_attrs(attrs: &[Attribute], property: bool) -> syn::Result<ScriptAttr> {
+    let mut script = ScriptAttr::default();
+    for attr in attrs {
+        // a bare #[script] has no arguments to parse
+        if !attr.path().is_ident("script") || matches!(attr.meta, syn::Meta::Path(_)) {
+            continue;
+        }
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                script.name = Some(meta.value()?.parse()?);
+            } else if property && meta.path.is_ident("get") {
+                script.get = true;
+            } else if property && meta.path.is_ident("set") {
+                script.set = true;
+            } else if property {
+                return Err(meta.error("unknown script attribute, expected `name`, `get` or `set`"));
+            } else {
+                return Err(meta.error("unknown script attribute, expected `name`"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(script)
+}
+
+/// Script members are PascalCase like the rest of VBScript, `add_ball` becomes `AddBall`.
+fn pascal_case(ident: &syn::Ident) -> String {
+    let ident = ident.to_string();
+    ident
+        .trim_start_matches("r#")
+        .split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
@@ -28,12 +28,6 @@ fn main() {
         .header(core_header_file_name)
         .header(pinmame_plugin_header_file_name)
         .header(logging_plugin_header_file_name)
-        // TODO enable this plugin, currently it requires -x c++
-        //.header(scriptable_plugin_header_file_name)
-        //.clang_arg("-Duint8_t=unsigned char")
-        //.clang_arg("-std=c99")
-        //.clang_arg("-x").clang_arg("c++")
-        //.clang_arg("-std=c++14")
         // Tell cargo to invalidate the built crate whenever any of the
         // included header files changed.
         .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
@@ -47,6 +41,21 @@ fn main() {
     bindings
         .write_to_file(out_path.join("bindings.rs"))
         .expect("Couldn't write bindings!");
+
+    // ScriptablePlugin.h only parses as C++, so it gets its own pass limited to its own types
+    let scriptable_bindings = bindgen::Builder::default()
+        .header(scriptable_plugin_header_file_name)
+        .clang_arg("-x")
+        .clang_arg("c++")
+        .clang_arg("-std=c++17")
+        .allowlist_type("Script.*")
+        .allowlist_var("SCRIPTPI_.*|PSC_.*")
+        .parse_callbacks(Box::new(bindgen::CargoCallbacks::new()))
+        .generate()
+        .expect("Unable to generate scriptable bindings");
+    scriptable_bindings
+        .write_to_file(out_path.join("scriptable_bindings.rs"))
+        .expect("Couldn't write scriptable bindings!");
 }
 
 fn download_header_file(header_file_name: &str) {
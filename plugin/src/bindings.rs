@@ -2,16 +2,88 @@ use crate::bindings;
 
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
+include!(concat!(env!("OUT_DIR"), "/scriptable_bindings.rs"));
+
+/// Unit of a plugin option. Every `VPXPluginAPI_OptionUnit_*` constant of the header has a
+/// variant, [`OptionUnit::Distance`] comes on top as the header has no unit for it.
+///
+/// Plugins work with values in their own unit, [`OptionUnit::to_host`] and
+/// [`OptionUnit::from_host`] convert to and from what the host stores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OptionUnit {
     None,
+    /// Shown as 0 to 100 %, stored by the host as a fraction from 0 to 1.
     Percent,
+    /// VPX units like in [`ViewSetup`](crate::ViewSetup), stored by the host as centimetres.
+    ///
+    /// Only exists on the Rust side: the host gets `VPXPluginAPI_OptionUnit_NONE` and shows
+    /// the value without a unit, so mention cm in the option name.
+    Distance,
+}
+
+impl OptionUnit {
+    pub const ALL: [OptionUnit; 3] = [OptionUnit::None, OptionUnit::Percent, OptionUnit::Distance];
+
+    pub fn to_host(self, value: f32) -> f32 {
+        match self {
+            OptionUnit::None => value,
+            OptionUnit::Percent => value / 100.0,
+            OptionUnit::Distance => value / crate::VPU_PER_CM,
+        }
+    }
+
+    pub fn from_host(self, value: f32) -> f32 {
+        match self {
+            OptionUnit::None => value,
+            OptionUnit::Percent => value * 100.0,
+            OptionUnit::Distance => value * crate::VPU_PER_CM,
+        }
+    }
 }
 
 impl From<OptionUnit> for VPXPluginAPI_OptionUnit {
     fn from(unit: OptionUnit) -> Self {
         match unit {
-            OptionUnit::None => VPXPluginAPI_OptionUnit_NONE,
+            OptionUnit::None | OptionUnit::Distance => VPXPluginAPI_OptionUnit_NONE,
             OptionUnit::Percent => VPXPluginAPI_OptionUnit_PERCENT,
         }
     }
 }
+
+impl TryFrom<VPXPluginAPI_OptionUnit> for OptionUnit {
+    /// The unknown host unit
+    type Error = VPXPluginAPI_OptionUnit;
+
+    fn try_from(unit: VPXPluginAPI_OptionUnit) -> Result<Self, Self::Error> {
+        OptionUnit::ALL
+            .into_iter()
+            .find(|known| VPXPluginAPI_OptionUnit::from(*known) == unit)
+            .ok_or(unit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_option_unit() {
+        for (value, unit) in [
+            (VPXPluginAPI_OptionUnit_NONE, OptionUnit::None),
+            (VPXPluginAPI_OptionUnit_PERCENT, OptionUnit::Percent),
+        ] {
+            assert_eq!(OptionUnit::try_from(value), Ok(unit));
+            assert_eq!(VPXPluginAPI_OptionUnit::from(unit), value);
+        }
+        assert_eq!(
+            VPXPluginAPI_OptionUnit::from(OptionUnit::Distance),
+            VPXPluginAPI_OptionUnit_NONE
+        );
+        for unit in OptionUnit::ALL {
+            assert!((unit.from_host(unit.to_host(50.0)) - 50.0).abs() < 1e-4);
+        }
+        assert_eq!(OptionUnit::try_from(1234), Err(1234));
+        assert_eq!(OptionUnit::Percent.to_host(50.0), 0.5);
+        assert_eq!(OptionUnit::Distance.from_host(2.0), 2.0 * crate::VPU_PER_CM);
+    }
+}
@@ -0,0 +1,328 @@
+use crate::bindings;
+use crate::error::VpxError;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// A function of the host api tables that may be missing on older VPX builds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HostFunction {
+    // MsgPluginAPI
+    GetMsgID,
+    SubscribeMsg,
+    UnsubscribeMsg,
+    BroadcastMsg,
+    ReleaseMsgID,
+    GetSetting,
+    RunOnMainThread,
+    // VPXPluginAPI
+    GetTableInfo,
+    GetOption,
+    PushNotification,
+    UpdateNotification,
+    DisableStaticPrerendering,
+    GetActiveViewSetup,
+    SetActiveViewSetup,
+}
+
+impl HostFunction {
+    pub const ALL: [HostFunction; 14] = [
+        HostFunction::GetMsgID,
+        HostFunction::SubscribeMsg,
+        HostFunction::UnsubscribeMsg,
+        HostFunction::BroadcastMsg,
+        HostFunction::ReleaseMsgID,
+        HostFunction::GetSetting,
+        HostFunction::RunOnMainThread,
+        HostFunction::GetTableInfo,
+        HostFunction::GetOption,
+        HostFunction::PushNotification,
+        HostFunction::UpdateNotification,
+        HostFunction::DisableStaticPrerendering,
+        HostFunction::GetActiveViewSetup,
+        HostFunction::SetActiveViewSetup,
+    ];
+
+    /// Name of the field in the C api table.
+    pub const fn name(self) -> &'static str {
+        match self {
+            HostFunction::GetMsgID => "GetMsgID",
+            HostFunction::SubscribeMsg => "SubscribeMsg",
+            HostFunction::UnsubscribeMsg => "UnsubscribeMsg",
+            HostFunction::BroadcastMsg => "BroadcastMsg",
+            HostFunction::ReleaseMsgID => "ReleaseMsgID",
+            HostFunction::GetSetting => "GetSetting",
+            HostFunction::RunOnMainThread => "RunOnMainThread",
+            HostFunction::GetTableInfo => "GetTableInfo",
+            HostFunction::GetOption => "GetOption",
+            HostFunction::PushNotification => "PushNotification",
+            HostFunction::UpdateNotification => "UpdateNotification",
+            HostFunction::DisableStaticPrerendering => "DisableStaticPrerendering",
+            HostFunction::GetActiveViewSetup => "GetActiveViewSetup",
+            HostFunction::SetActiveViewSetup => "SetActiveViewSetup",
+        }
+    }
+
+    const fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+impl Display for HostFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Version of the VPX plugin api, as used for `vpx_api` in plugin.cfg.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HostVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl HostVersion {
+    /// Newest version a host can be detected as, see [`HostCapabilities::version`].
+    pub const LATEST: HostVersion = KNOWN_VERSIONS[KNOWN_VERSIONS.len() - 1].0;
+
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// `Ord` for const contexts, used to check `MIN_HOST_VERSION` at compile time.
+    pub const fn newer_than(self, other: HostVersion) -> bool {
+        if self.major != other.major {
+            return self.major > other.major;
+        }
+        if self.minor != other.minor {
+            return self.minor > other.minor;
+        }
+        self.patch > other.patch
+    }
+}
+
+impl Display for HostVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+impl FromStr for HostVersion {
+    type Err = String;
+
+    /// Parses `major.minor[.patch]`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s
+            .trim()
+            .split('.')
+            .map(|part| part.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|error| format!("invalid version \"{s}\": {error}"))?;
+        match parts[..] {
+            [major, minor] => Ok(Self::new(major, minor, 0)),
+            [major, minor, patch] => Ok(Self::new(major, minor, patch)),
+            _ => Err(format!(
+                "invalid version \"{s}\": expected major.minor[.patch]"
+            )),
+        }
+    }
+}
+
+/// Plugin api versions with the functions every host of that version provides, oldest first.
+///
+/// The host does not report its version, so it is derived from the functions in its api
+/// tables. Versions older than the first one can not be told apart.
+const KNOWN_VERSIONS: &[(HostVersion, &[HostFunction])] =
+    &[(HostVersion::new(10, 8, 1), &HostFunction::ALL)];
+
+/// What the host provides, computed once when the plugin loads.
+///
+/// Api methods fail with [`VpxError::MissingFunction`] exactly for the functions that
+/// [`HostCapabilities::has`] reports as missing, so plugins can check up front instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct HostCapabilities {
+    functions: u32,
+    vpx_api: bool,
+    version: Option<HostVersion>,
+}
+
+impl HostCapabilities {
+    /// `vpx` may be null if the host did not hand out the VPX api.
+    pub(crate) fn detect(
+        msg: *const bindings::MsgPluginAPI,
+        vpx: *const bindings::VPXPluginAPI,
+    ) -> Self {
+        let mut capabilities = Self::default();
+        if let Some(msg) = unsafe { msg.as_ref() } {
+            capabilities.set(HostFunction::GetMsgID, msg.GetMsgID.is_some());
+            capabilities.set(HostFunction::SubscribeMsg, msg.SubscribeMsg.is_some());
+            capabilities.set(HostFunction::UnsubscribeMsg, msg.UnsubscribeMsg.is_some());
+            capabilities.set(HostFunction::BroadcastMsg, msg.BroadcastMsg.is_some());
+            capabilities.set(HostFunction::ReleaseMsgID, msg.ReleaseMsgID.is_some());
+            capabilities.set(HostFunction::GetSetting, msg.GetSetting.is_some());
+            capabilities.set(HostFunction::RunOnMainThread, msg.RunOnMainThread.is_some());
+        }
+        if let Some(vpx) = unsafe { vpx.as_ref() } {
+            capabilities.vpx_api = true;
+            capabilities.set(HostFunction::GetTableInfo, vpx.GetTableInfo.is_some());
+            capabilities.set(HostFunction::GetOption, vpx.GetOption.is_some());
+            capabilities.set(
+                HostFunction::PushNotification,
+                vpx.PushNotification.is_some(),
+            );
+            capabilities.set(
+                HostFunction::UpdateNotification,
+                vpx.UpdateNotification.is_some(),
+            );
+            capabilities.set(
+                HostFunction::DisableStaticPrerendering,
+                vpx.DisableStaticPrerendering.is_some(),
+            );
+            capabilities.set(
+                HostFunction::GetActiveViewSetup,
+                vpx.GetActiveViewSetup.is_some(),
+            );
+            capabilities.set(
+                HostFunction::SetActiveViewSetup,
+                vpx.SetActiveViewSetup.is_some(),
+            );
+        }
+        capabilities.version = KNOWN_VERSIONS
+            .iter()
+            .rev()
+            .find(|(_, functions)| functions.iter().all(|f| capabilities.has(*f)))
+            .map(|(version, _)| *version);
+        capabilities
+    }
+
+    fn set(&mut self, function: HostFunction, present: bool) {
+        if present {
+            self.functions |= function.bit();
+        } else {
+            self.functions &= !function.bit();
+        }
+    }
+
+    pub fn has(&self, function: HostFunction) -> bool {
+        self.functions & function.bit() != 0
+    }
+
+    pub fn missing(&self) -> impl Iterator<Item = HostFunction> + '_ {
+        HostFunction::ALL.into_iter().filter(|f| !self.has(*f))
+    }
+
+    /// Whether the host handed out the VPX plugin api at all.
+    pub fn has_vpx_api(&self) -> bool {
+        self.vpx_api
+    }
+
+    /// Newest api version the host provides all functions of, `None` if it is older than any
+    /// version this crate knows. Hosts newer than [`HostVersion::LATEST`] are detected as
+    /// [`HostVersion::LATEST`].
+    pub fn version(&self) -> Option<HostVersion> {
+        self.version
+    }
+
+    /// Checks the plugin requirements, see [`crate::Plugin::MIN_HOST_VERSION`] and
+    /// [`crate::Plugin::REQUIRED_FUNCTIONS`].
+    pub(crate) fn check(
+        &self,
+        min_version: Option<HostVersion>,
+        required: &[HostFunction],
+    ) -> Result<(), VpxError> {
+        if let Some(min_version) = min_version {
+            if self.version.is_none_or(|version| version < min_version) {
+                return Err(VpxError::HostTooOld {
+                    required: min_version,
+                    found: self.version,
+                });
+            }
+        }
+        if let Some(function) = required.iter().find(|f| !self.has(**f)) {
+            return Err(VpxError::MissingFunction(function.name()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{TestMsgPluginAPI, TestVPXPluginAPI};
+
+    const V10_8_1: HostVersion = HostVersion::new(10, 8, 1);
+
+    #[test]
+    fn test_detect_and_check() {
+        let vpx_api = TestVPXPluginAPI::init();
+        let msg_api = TestMsgPluginAPI::init(&vpx_api);
+
+        let capabilities = HostCapabilities::detect(&msg_api, std::ptr::null());
+        assert!(!capabilities.has_vpx_api());
+        assert!(capabilities.has(HostFunction::GetSetting));
+        assert!(!capabilities.has(HostFunction::GetOption));
+
+        let capabilities = HostCapabilities::detect(&msg_api, &vpx_api);
+        assert!(capabilities.has(HostFunction::GetOption));
+        assert_eq!(
+            capabilities.missing().collect::<Vec<_>>(),
+            vec![
+                HostFunction::PushNotification,
+                HostFunction::UpdateNotification,
+                HostFunction::DisableStaticPrerendering,
+                HostFunction::GetActiveViewSetup,
+                HostFunction::SetActiveViewSetup,
+            ]
+        );
+
+        assert_eq!(capabilities.version(), None);
+
+        assert_eq!(capabilities.check(None, &[HostFunction::GetOption]), Ok(()));
+        assert_eq!(
+            capabilities.check(None, &[HostFunction::PushNotification]),
+            Err(VpxError::MissingFunction("PushNotification"))
+        );
+        assert_eq!(
+            capabilities.check(Some(V10_8_1), &[]),
+            Err(VpxError::HostTooOld {
+                required: V10_8_1,
+                found: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_detect_version() {
+        let mut vpx_api = TestVPXPluginAPI::init();
+        TestVPXPluginAPI::enable_notifications(&mut vpx_api);
+        TestVPXPluginAPI::enable_static_prerendering(&mut vpx_api);
+        TestVPXPluginAPI::enable_view_setup(&mut vpx_api);
+        let msg_api = TestMsgPluginAPI::init(&vpx_api);
+
+        let capabilities = HostCapabilities::detect(&msg_api, &vpx_api);
+        assert_eq!(capabilities.version(), Some(HostVersion::LATEST));
+        assert_eq!(capabilities.check(Some(V10_8_1), &[]), Ok(()));
+        assert_eq!(
+            capabilities.check(Some(HostVersion::new(10, 9, 0)), &[]),
+            Err(VpxError::HostTooOld {
+                required: HostVersion::new(10, 9, 0),
+                found: Some(V10_8_1),
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!("10.8.1".parse(), Ok(V10_8_1));
+        assert_eq!("10.8".parse(), Ok(HostVersion::new(10, 8, 0)));
+        assert!("10".parse::<HostVersion>().is_err());
+        assert!("10.x".parse::<HostVersion>().is_err());
+        assert!(V10_8_1 > HostVersion::new(10, 7, 9));
+        assert!(V10_8_1.newer_than(HostVersion::new(10, 7, 9)));
+        assert!(!V10_8_1.newer_than(V10_8_1));
+    }
+}
@@ -0,0 +1,144 @@
+use crate::bindings::{self, DmdSrcId, GetDmdSrcMsg};
+use crate::messages::GetDmdSources;
+use crate::{VPXApi, VpxError};
+
+/// Room for this many sources on the first request, enough for any table we know of.
+const INITIAL_CAPACITY: usize = 8;
+/// More sources than any controller has, a larger count is not trusted.
+const MAX_CAPACITY: usize = 256;
+/// Sources can be added between requests, give up when the count keeps changing.
+const MAX_REQUESTS: usize = 3;
+
+/// Pixel format of a DMD source, one variant per `CTLPI_GETDMD_FORMAT_*` constant.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DmdFormat {
+    /// One byte of luminance per pixel
+    Lum8,
+    /// Three bytes of sRGB color per pixel
+    Srgb888,
+    /// A format added to the host after this crate was written
+    Unknown(u32),
+}
+
+impl From<u32> for DmdFormat {
+    fn from(format: u32) -> Self {
+        match format {
+            bindings::CTLPI_GETDMD_FORMAT_LUM8 => DmdFormat::Lum8,
+            bindings::CTLPI_GETDMD_FORMAT_SRGB888 => DmdFormat::Srgb888,
+            other => DmdFormat::Unknown(other),
+        }
+    }
+}
+
+/// A DMD a controller plugin like PinMAME can render.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmdSource {
+    /// Identifies the source when asking the controller for frames.
+    pub id: u32,
+    pub width: u32,
+    pub height: u32,
+    pub format: DmdFormat,
+}
+
+impl From<&DmdSrcId> for DmdSource {
+    fn from(source: &DmdSrcId) -> Self {
+        Self {
+            id: source.id,
+            width: source.width,
+            height: source.height,
+            format: DmdFormat::from(source.format),
+        }
+    }
+}
+
+/// Controllers add their sources while there is room and count all of them, so another
+/// request with enough room is made when the first one was too small.
+pub(crate) fn dmd_sources(api: &dyn VPXApi) -> Result<Vec<DmdSource>, VpxError> {
+    let mut capacity = INITIAL_CAPACITY;
+    for _ in 0..MAX_REQUESTS {
+        let mut entries = vec![
+            DmdSrcId {
+                id: 0,
+                width: 0,
+                height: 0,
+                format: 0,
+            };
+            capacity
+        ];
+        let mut msg = GetDmdSrcMsg {
+            maxEntryCount: u32::try_from(capacity)
+                .map_err(|_| VpxError::InvalidValue("dmd source count"))?,
+            count: 0,
+            entries: entries.as_mut_ptr(),
+        };
+        api.broadcast_with::<GetDmdSources>(&mut msg)?;
+        let count = msg.count as usize;
+        if count <= capacity {
+            return Ok(entries[..count].iter().map(DmdSource::from).collect());
+        }
+        if count > MAX_CAPACITY {
+            return Err(VpxError::NoData("a plausible number of dmd sources"));
+        }
+        capacity = count;
+    }
+    Err(VpxError::NoData("a stable number of dmd sources"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::DmdSourcesChanged;
+    use crate::test::{wrapped_api, TestMsgPluginAPI};
+    use crate::Message;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_enumerate_sources() {
+        let api = wrapped_api();
+        let api: &dyn VPXApi = &*api;
+        assert_eq!(api.dmd_sources(), Ok(Vec::new()));
+
+        let changes = Rc::new(Cell::new(0));
+        let changes_clone = Rc::clone(&changes);
+        let _subscription = api
+            .subscribe::<DmdSourcesChanged>(move |_| changes_clone.set(changes_clone.get() + 1))
+            .unwrap();
+        TestMsgPluginAPI::add_dmd_source(1, 128, 32, bindings::CTLPI_GETDMD_FORMAT_LUM8);
+        // more than fit in the first request
+        for id in 2..=INITIAL_CAPACITY as u32 + 2 {
+            TestMsgPluginAPI::add_dmd_source(id, 256, 64, 7);
+        }
+        let msg_id = api
+            .get_msg_id(DmdSourcesChanged::NAMESPACE, DmdSourcesChanged::NAME)
+            .unwrap();
+        TestMsgPluginAPI::broadcast(msg_id.raw());
+        assert_eq!(changes.get(), 1);
+
+        let sources = api.dmd_sources().unwrap();
+        assert_eq!(sources.len(), INITIAL_CAPACITY + 2);
+        assert_eq!(
+            sources[0],
+            DmdSource {
+                id: 1,
+                width: 128,
+                height: 32,
+                format: DmdFormat::Lum8,
+            }
+        );
+        assert_eq!(sources.last().unwrap().format, DmdFormat::Unknown(7));
+    }
+
+    #[test]
+    fn test_too_many_sources() {
+        let api = wrapped_api();
+        let api: &dyn VPXApi = &*api;
+        for id in 0..=MAX_CAPACITY as u32 {
+            TestMsgPluginAPI::add_dmd_source(id, 128, 32, bindings::CTLPI_GETDMD_FORMAT_LUM8);
+        }
+        assert_eq!(
+            api.dmd_sources(),
+            Err(VpxError::NoData("a plausible number of dmd sources"))
+        );
+    }
+}
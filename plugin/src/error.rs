@@ -0,0 +1,138 @@
+use crate::HostVersion;
+use std::ffi::NulError;
+use std::fmt::{Display, Formatter};
+use std::str::Utf8Error;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VpxError {
+    /// The host does not provide this function, usually because it is an older VPX build
+    MissingFunction(&'static str),
+    /// The host did not hand out the VPX plugin api
+    ApiUnavailable,
+    /// The function can only be called between game start and game end
+    NotInGame,
+    /// The plugin was unloaded before the call could be made
+    Unloaded,
+    /// A string passed to the host contains a NUL byte
+    InteriorNul,
+    /// A string returned by the host is not valid UTF-8
+    NotUtf8,
+    /// The host did not fill in the requested data
+    NoData(&'static str),
+    /// A value passed to the host is out of range, names the field
+    InvalidValue(&'static str),
+    /// The host provides an older plugin api than the plugin requires, `found` is `None` for
+    /// hosts older than any version we know
+    HostTooOld {
+        required: HostVersion,
+        found: Option<HostVersion>,
+    },
+    /// An object was created for a script class that is not registered, names the type
+    UnregisteredClass(&'static str),
+}
+
+impl Display for VpxError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VpxError::MissingFunction(name) => {
+                write!(f, "host does not provide the {name} function")
+            }
+            VpxError::ApiUnavailable => write!(f, "host did not provide the VPX plugin api"),
+            VpxError::NotInGame => write!(f, "only available while a game is running"),
+            VpxError::Unloaded => write!(f, "plugin is not loaded"),
+            VpxError::InteriorNul => write!(f, "string contains a NUL byte"),
+            VpxError::NotUtf8 => write!(f, "host returned a string that is not valid UTF-8"),
+            VpxError::NoData(what) => write!(f, "host did not provide {what}"),
+            VpxError::InvalidValue(field) => write!(f, "invalid value for {field}"),
+            VpxError::HostTooOld { required, found } => {
+                write!(f, "plugin requires VPX plugin api {required} or newer, ")?;
+                match found {
+                    Some(found) => write!(f, "host provides {found}"),
+                    None => write!(f, "host provides an older one"),
+                }
+            }
+            VpxError::UnregisteredClass(name) => {
+                write!(f, "script class {name} is not registered")
+            }
+        }
+    }
+}
+
+impl std::error::Error for VpxError {}
+
+impl From<NulError> for VpxError {
+    fn from(_: NulError) -> Self {
+        VpxError::InteriorNul
+    }
+}
+
+impl From<Utf8Error> for VpxError {
+    fn from(_: Utf8Error) -> Self {
+        VpxError::NotUtf8
+    }
+}
+
+/// Turns a function pointer from the host api tables into a [`VpxError::MissingFunction`]
+/// when the host does not provide it.
+pub(crate) fn require<F>(function: Option<F>, name: &'static str) -> Result<F, VpxError> {
+    function.ok_or(VpxError::MissingFunction(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::GameEnd;
+    use crate::test::{wrapped_api, TestMsgPluginAPI};
+    use crate::{VPXApi, VPXPI_EVT_ON_GAME_END, VPXPI_EVT_ON_GAME_START, VPXPI_NAMESPACE};
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_errors() {
+        let mut api = wrapped_api();
+        let vpx_api = api.vpx_api();
+        api.set_vpx(std::ptr::null_mut());
+        assert_eq!(
+            api.push_notification("hello", 1000).err(),
+            Some(VpxError::ApiUnavailable)
+        );
+
+        api.set_vpx(vpx_api);
+        let api: &dyn VPXApi = &*api;
+        assert_eq!(
+            api.push_notification("hello", 1000).err(),
+            Some(VpxError::MissingFunction("PushNotification"))
+        );
+        assert_eq!(
+            api.get_msg_id("VPX", "On\0GameStart"),
+            Err(VpxError::InteriorNul)
+        );
+
+        assert_eq!(api.get_table_info().err(), Some(VpxError::NotInGame));
+        // game end callbacks of the plugin still run inside the game
+        let in_game_at_end = Rc::new(Cell::new(false));
+        let in_game_at_end_clone = Rc::clone(&in_game_at_end);
+        let plugin_api: *const dyn VPXApi = api;
+        let _end = api
+            .subscribe::<GameEnd>(move |_| {
+                let table_info = unsafe { (*plugin_api).get_table_info() };
+                in_game_at_end_clone.set(table_info.err() != Some(VpxError::NotInGame));
+            })
+            .unwrap();
+        let start = api
+            .get_msg_id(VPXPI_NAMESPACE, VPXPI_EVT_ON_GAME_START)
+            .unwrap();
+        let end = api
+            .get_msg_id(VPXPI_NAMESPACE, VPXPI_EVT_ON_GAME_END)
+            .unwrap();
+        TestMsgPluginAPI::broadcast(start.raw());
+        // the test host does not fill in the path
+        assert_eq!(
+            api.get_table_info().err(),
+            Some(VpxError::NoData("table path"))
+        );
+        TestMsgPluginAPI::broadcast(end.raw());
+        assert!(in_game_at_end.get());
+        assert_eq!(api.get_table_info().err(), Some(VpxError::NotInGame));
+    }
+}
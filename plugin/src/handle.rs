@@ -0,0 +1,192 @@
+use crate::error::{require, VpxError};
+use crate::panic::PanicState;
+use crate::{bindings, VPXApi, WrappedPluginApi};
+use log::debug;
+use std::ffi::c_void;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread::{self, ThreadId};
+use std::time::Duration;
+
+pub(crate) struct HandleShared {
+    msg: *mut bindings::MsgPluginAPI,
+    /// Set while the plugin is loaded, only dereferenced on the main thread.
+    api: AtomicPtr<WrappedPluginApi>,
+    main_thread: ThreadId,
+    panic: Arc<PanicState>,
+}
+
+// The host function table never changes and `RunOnMainThread` may be called from any thread,
+// the api pointer itself is only dereferenced on the main thread.
+unsafe impl Send for HandleShared {}
+unsafe impl Sync for HandleShared {}
+
+impl HandleShared {
+    pub(crate) fn new(msg: *mut bindings::MsgPluginAPI, panic: Arc<PanicState>) -> Self {
+        Self {
+            msg,
+            api: AtomicPtr::new(std::ptr::null_mut()),
+            main_thread: thread::current().id(),
+            panic,
+        }
+    }
+
+    pub(crate) fn attach(&self, api: *mut WrappedPluginApi) {
+        self.api.store(api, Ordering::Release);
+    }
+
+    pub(crate) fn detach(&self) {
+        self.api.store(std::ptr::null_mut(), Ordering::Release);
+    }
+
+    pub(crate) fn is_main_thread(&self) -> bool {
+        thread::current().id() == self.main_thread
+    }
+
+    /// Runs the job with the api if the plugin is still loaded, must be called on the main thread.
+    fn run(&self, job: Job) {
+        let api = self.api.load(Ordering::Acquire);
+        if api.is_null() {
+            debug!("Plugin unloaded, dropping main thread job");
+            return;
+        }
+        self.panic
+            .guard("main thread job", || job(unsafe { &*api }));
+    }
+}
+
+type Job = Box<dyn FnOnce(&dyn VPXApi) + Send>;
+
+struct Ticket {
+    shared: Arc<HandleShared>,
+    job: Job,
+}
+
+/// Handle to the plugin api that can be sent to and shared between worker threads.
+///
+/// The [`VPXApi`] itself may only be used on the main thread, the handle forwards closures to
+/// the main thread through the host's `RunOnMainThread`. Closures that are still pending when
+/// the plugin unloads are dropped without running.
+#[derive(Clone)]
+pub struct ApiHandle {
+    shared: Arc<HandleShared>,
+}
+
+impl ApiHandle {
+    pub(crate) fn new(shared: Arc<HandleShared>) -> Self {
+        Self { shared }
+    }
+
+    pub fn is_loaded(&self) -> bool {
+        !self.shared.api.load(Ordering::Acquire).is_null()
+    }
+
+    /// Runs the closure with the api on the main thread after the delay.
+    pub fn run_on_main_thread(
+        &self,
+        delay: Duration,
+        task: impl FnOnce(&dyn VPXApi) + Send + 'static,
+    ) -> Result<(), VpxError> {
+        let run_on_main_thread = require(
+            unsafe { (*self.shared.msg).RunOnMainThread },
+            "RunOnMainThread",
+        )?;
+        let ticket = Box::new(Ticket {
+            shared: Arc::clone(&self.shared),
+            job: Box::new(task),
+        });
+        unsafe {
+            run_on_main_thread(
+                delay.as_secs_f64(),
+                Some(handle_trampoline),
+                Box::into_raw(ticket) as *mut c_void,
+            );
+        }
+        Ok(())
+    }
+
+    /// Runs the closure on the main thread and waits for its result.
+    ///
+    /// Runs directly when called on the main thread. Fails with [`VpxError::Unloaded`] if the
+    /// plugin unloaded before the closure could run. Blocks until the host processes its main
+    /// thread queue.
+    pub fn call<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&dyn VPXApi) -> R + Send + 'static,
+    ) -> Result<R, VpxError> {
+        if self.shared.is_main_thread() {
+            let api = self.shared.api.load(Ordering::Acquire);
+            if api.is_null() {
+                return Err(VpxError::Unloaded);
+            }
+            return Ok(f(unsafe { &*api }));
+        }
+        let (sender, receiver) = mpsc::sync_channel(1);
+        self.run_on_main_thread(Duration::ZERO, move |api| {
+            let _ = sender.send(f(api));
+        })?;
+        // the sender is dropped without sending if the job gets dropped on unload
+        receiver.recv().map_err(|_| VpxError::Unloaded)
+    }
+
+    /// Like [`ApiHandle::call`] without the `Send` bounds, only for use on the main thread.
+    pub(crate) fn with_api<R>(&self, f: impl FnOnce(&dyn VPXApi) -> R) -> Result<R, VpxError> {
+        debug_assert!(self.shared.is_main_thread());
+        let api = self.shared.api.load(Ordering::Acquire);
+        if api.is_null() {
+            return Err(VpxError::Unloaded);
+        }
+        Ok(f(unsafe { &*api }))
+    }
+}
+
+unsafe extern "C" fn handle_trampoline(user_data: *mut c_void) {
+    let ticket = Box::from_raw(user_data as *mut Ticket);
+    ticket.shared.run(ticket.job);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{wrapped_api, TestMsgPluginAPI, TEST_SESSION_ID};
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn test_forward_from_worker_thread() {
+        let api = wrapped_api();
+        let handle = api.handle();
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let calls_clone = Arc::clone(&calls);
+        thread::spawn(move || {
+            handle
+                .run_on_main_thread(Duration::ZERO, move |api| {
+                    assert_eq!(api.endpoint_id(), TEST_SESSION_ID);
+                    calls_clone.fetch_add(1, Ordering::SeqCst);
+                })
+                .unwrap();
+        })
+        .join()
+        .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        TestMsgPluginAPI::run_pending_from_threads();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // jobs still queued on unload are dropped
+        let handle = api.handle();
+        let calls_clone = Arc::clone(&calls);
+        thread::spawn(move || {
+            handle
+                .run_on_main_thread(Duration::ZERO, move |_| {
+                    calls_clone.fetch_add(1, Ordering::SeqCst);
+                })
+                .unwrap();
+        })
+        .join()
+        .unwrap();
+        api.handle_shared.detach();
+        TestMsgPluginAPI::run_pending_from_threads();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,112 @@
+use crate::VpxError;
+use log::debug;
+use std::cell::RefCell;
+use std::ffi::{c_char, CString};
+
+/// Labels of an option in the layout `GetOption` expects: a null terminated array of C
+/// strings.
+///
+/// Moving a `LabelSet` does not move the strings or the array, so the pointer stays valid for
+/// as long as the set lives.
+#[derive(Debug)]
+pub struct LabelSet {
+    labels: Vec<CString>,
+    pointers: Vec<*const c_char>,
+}
+
+impl LabelSet {
+    pub fn new(labels: &[&str]) -> Result<Self, VpxError> {
+        let labels = labels
+            .iter()
+            .map(|label| CString::new(*label))
+            .collect::<Result<Vec<_>, _>>()?;
+        let pointers = labels
+            .iter()
+            .map(|label| label.as_ptr())
+            .chain(std::iter::once(std::ptr::null()))
+            .collect();
+        Ok(Self { labels, pointers })
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    pub fn matches(&self, labels: &[&str]) -> bool {
+        self.labels.len() == labels.len()
+            && self
+                .labels
+                .iter()
+                .zip(labels)
+                .all(|(a, b)| a.as_bytes() == b.as_bytes())
+    }
+
+    /// The host api takes a mutable pointer but never writes through it.
+    pub fn as_ptr(&self) -> *mut *const c_char {
+        self.pointers.as_ptr() as *mut *const c_char
+    }
+}
+
+/// Keeps the labels handed to the host alive until the plugin unloads, the host may hold on
+/// to them for as long as it shows the option.
+#[derive(Default)]
+pub(crate) struct LabelStore {
+    sets: RefCell<Vec<LabelSet>>,
+}
+
+impl LabelStore {
+    /// Returns the pointer of an identical set handed out before, or stores a new one.
+    pub(crate) fn intern(&self, labels: &[&str]) -> Result<*mut *const c_char, VpxError> {
+        let mut sets = self.sets.borrow_mut();
+        if let Some(set) = sets.iter().find(|set| set.matches(labels)) {
+            return Ok(set.as_ptr());
+        }
+        let set = LabelSet::new(labels)?;
+        let ptr = set.as_ptr();
+        sets.push(set);
+        Ok(ptr)
+    }
+
+    pub(crate) fn clear(&self) {
+        let sets = self.sets.take();
+        if !sets.is_empty() {
+            debug!("Releasing {} option label set(s)", sets.len());
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.sets.borrow().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn test_label_store() {
+        let store = LabelStore::default();
+        let ptr = store.intern(&["Red", "Blue"]).unwrap();
+        assert_eq!(store.intern(&["Red", "Blue"]).unwrap(), ptr);
+        assert_ne!(store.intern(&["Red"]).unwrap(), ptr);
+        assert_eq!(store.len(), 2);
+        assert_eq!(store.intern(&["R\0ed"]), Err(VpxError::InteriorNul));
+
+        let labels = unsafe {
+            (0..)
+                .map(|i| *ptr.add(i))
+                .take_while(|label| !label.is_null())
+                .map(|label| CStr::from_ptr(label).to_str().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(labels, vec!["Red", "Blue"]);
+
+        store.clear();
+        assert_eq!(store.len(), 0);
+    }
+}
@@ -6,13 +6,70 @@
 #![allow(clippy::upper_case_acronyms)]
 
 pub mod bindings;
+mod capabilities;
+mod dmd;
+mod error;
+mod handle;
+mod labels;
+mod logging;
+pub mod messages;
+mod msg;
+mod notifications;
+pub mod options;
+mod panic;
+mod prerender;
+mod scheduler;
+mod scriptable;
+mod session;
+mod settings;
 pub mod test;
-
-use log::{info, warn};
+mod view;
+
+pub use capabilities::{HostCapabilities, HostFunction, HostVersion};
+pub use dmd::{DmdFormat, DmdSource};
+pub use error::VpxError;
+pub use handle::ApiHandle;
+pub use labels::LabelSet;
+pub use logging::init_logging;
+pub use messages::{Message, MessagePayload, Request};
+pub use msg::{MsgId, Subscription};
+pub use notifications::{LiveNotification, NotificationHandle};
+pub use options::{OptionChoice, OptionDef, OptionValue, Options, PluginOptions};
+pub use panic::catch_panic;
+pub use prerender::StaticPrerenderGuard;
+pub use scheduler::{TaskHandle, Timer};
+pub use scriptable::{
+    ArrayType, ArrayValue, ObjectType, ScriptClass, ScriptClassDefinition, ScriptClassHandle,
+    ScriptData, ScriptMethods, ScriptObject, ScriptRef, ScriptReturn, ScriptType, ScriptValue,
+    Scriptable,
+};
+pub use session::{GameSession, GameState};
+pub use settings::SettingError;
+pub use view::{ViewMode, ViewSetup, VPU_PER_CM};
+pub use vpinball_plugin_derive::{script_methods, OptionChoice, PluginOptions, ScriptClass};
+
+// lets the derive macros refer to this crate by name, also from within this crate
+extern crate self as vpinball_plugin_api;
+
+use error::require;
+use handle::HandleShared;
+use labels::LabelStore;
+use log::{error, info, warn};
+use msg::MsgBus;
+use notifications::Notifier;
+use panic::PanicState;
+use prerender::Prerender;
+use scheduler::Scheduler;
+use scriptable::Scripting;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::ffi::{c_uint, CStr, CString};
-use std::fmt::Debug;
+use std::fmt::{Debug, Display};
 use std::os::raw::{c_char, c_void};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 
 // we redefine the constants here to avoid the need to translate from C to Rust
 // MsgPlugin
@@ -29,14 +86,29 @@ pub const PMPI_NAMESPACE: &str = cstr_to_str(bindings::PMPI_NAMESPACE);
 pub const PMPI_EVT_ON_GAME_START: &str = cstr_to_str(bindings::PMPI_EVT_ON_GAME_START);
 pub const PMPI_EVT_ON_GAME_END: &str = cstr_to_str(bindings::PMPI_EVT_ON_GAME_END);
 
+// LoggingPlugin
+pub const LPI_NAMESPACE: &str = cstr_to_str(bindings::LPI_NAMESPACE);
+pub const LPI_MSG_GET_API: &str = cstr_to_str(bindings::LPI_MSG_GET_API);
+
+// ScriptablePlugin
+pub const SCRIPTPI_NAMESPACE: &str = cstr_to_str(bindings::SCRIPTPI_NAMESPACE);
+pub const SCRIPTPI_MSG_GET_API: &str = cstr_to_str(bindings::SCRIPTPI_MSG_GET_API);
+
 // CorePlugin
 pub const CTLPI_NAMESPACE: &str = cstr_to_str(bindings::CTLPI_NAMESPACE);
 pub const CTLPI_GETDMD_SRC_MSG: &str = cstr_to_str(bindings::CTLPI_GETDMD_SRC_MSG);
 pub const CTLPI_GETDMD_RENDER_MSG: &str = cstr_to_str(bindings::CTLPI_GETDMD_RENDER_MSG);
 pub const CTLPI_GETDMD_IDENTIFY_MSG: &str = cstr_to_str(bindings::CTLPI_GETDMD_IDENTIFY_MSG);
+pub const CTLPI_ONDMD_SRC_CHANGED_MSG: &str = cstr_to_str(bindings::CTLPI_ONDMD_SRC_CHANGED_MSG);
 
+/// Access to the host, all methods report a missing host function as
+/// [`VpxError::MissingFunction`] instead of crashing.
 pub trait VPXApi {
-    fn get_table_info(&self) -> TableInfo;
+    /// Only available while a game is running.
+    fn get_table_info(&self) -> Result<TableInfo, VpxError>;
+
+    /// Registers an option and returns its raw value as stored by the host, prefer
+    /// `#[derive(PluginOptions)]` which converts values for the unit.
     fn get_option(
         &self,
         page_id: &str,
@@ -49,115 +121,499 @@ pub trait VPXApi {
         default_value: f32,
         unit: bindings::OptionUnit,
         values: &[&str],
-    ) -> f32;
+    ) -> Result<f32, VpxError>;
+
+    /// Shows a notification, the handle can be used to change or dismiss it.
+    fn push_notification(
+        &self,
+        message: &str,
+        length_ms: u32,
+    ) -> Result<NotificationHandle, VpxError>;
+
+    /// A notification that is updated in place by every `show()`, for events that fire often.
+    /// Updates closer together than `min_interval` are coalesced.
+    fn live_notification(&self, length: Duration, min_interval: Duration) -> LiveNotification;
 
-    fn push_notification(&self, message: &str, length_ms: u32);
+    /// Looks up the id of a message, the id stays valid until the returned handle is dropped
+    /// or the plugin unloads.
+    fn get_msg_id(&self, msg_name_space: &str, msg_name: &str) -> Result<MsgId, VpxError>;
 
-    fn broadcast_msg(&self, endpoint_id: c_uint, msg_name_space: &str, msg_name: &str);
+    /// Endpoint id the host assigned to this plugin, used as sender when broadcasting.
+    fn endpoint_id(&self) -> c_uint;
+
+    /// The functions the host provides, detected when the plugin loads.
+    fn capabilities(&self) -> HostCapabilities;
+
+    fn broadcast_msg(
+        &self,
+        endpoint_id: c_uint,
+        msg_name_space: &str,
+        msg_name: &str,
+    ) -> Result<(), VpxError> {
+        unsafe {
+            self.broadcast_msg_data(endpoint_id, msg_name_space, msg_name, std::ptr::null_mut())
+        }
+    }
+
+    /// Broadcasts a message with a raw data pointer, prefer the typed `broadcast_with()`
+    /// and `query()`.
+    ///
+    /// # Safety
+    ///
+    /// `data` must point to whatever the receivers of the message expect.
+    unsafe fn broadcast_msg_data(
+        &self,
+        endpoint_id: c_uint,
+        msg_name_space: &str,
+        msg_name: &str,
+        data: *mut c_void,
+    ) -> Result<(), VpxError>;
+
+    /// Only available while a game is running.
+    fn get_active_view_setup(&self) -> Result<ViewSetup, VpxError>;
+
+    /// Replaces the camera setup of the running game, the setup is validated first.
+    fn set_active_view_setup(&self, setup: &ViewSetup) -> Result<(), VpxError>;
+
+    /// Disables static prerendering until the returned guard is dropped, for plugins that
+    /// move the view.
+    fn disable_static_prerendering(&self) -> Result<StaticPrerenderGuard, VpxError>;
+
+    /// Registers a class with the host ScriptablePlugin, prefer the typed
+    /// `register_script_class(class)`.
+    fn register_script_class_definition(
+        &self,
+        class: ScriptClassDefinition,
+    ) -> Result<ScriptClassHandle, VpxError>;
+
+    /// Returns a handle that can be moved to worker threads to call back into the api.
+    fn handle(&self) -> ApiHandle;
+
+    /// Runs the closure on the main thread after the delay, the task is dropped without
+    /// running if the plugin unloads first.
+    fn run_on_main_thread(
+        &self,
+        delay: Duration,
+        task: Box<dyn FnOnce()>,
+    ) -> Result<TaskHandle, VpxError>;
 
-    fn get_active_view_setup(&self) -> bindings::VPXViewSetupDef;
+    /// Calls the closure on the main thread every `interval` until the [`Timer`] is dropped.
+    fn timer(&self, interval: Duration, callback: Box<dyn FnMut()>) -> Result<Timer, VpxError>;
 
+    /// Reads a raw setting value from VPinballX.ini, `None` if the key is missing or empty.
+    fn get_setting_str(&self, name_space: &str, name: &str)
+        -> Result<Option<String>, SettingError>;
+
+    /// Registers a callback for a message, the callback stays registered until the returned
+    /// [`Subscription`] is dropped or the plugin unloads.
     fn subscribe_msg(
-        &mut self,
+        &self,
         msg_name_space: &str,
         msg_name: &str,
         callback_closure: Box<dyn Fn(u32)>,
-    );
+    ) -> Result<Subscription, VpxError> {
+        self.subscribe_msg_data(
+            msg_name_space,
+            msg_name,
+            Box::new(move |msg_id, _data| callback_closure(msg_id)),
+        )
+    }
+
+    /// Like [`VPXApi::subscribe_msg`] but also passes the raw data pointer of the message,
+    /// prefer the typed `subscribe::<M>()` for known messages.
+    fn subscribe_msg_data(
+        &self,
+        msg_name_space: &str,
+        msg_name: &str,
+        callback_closure: Box<dyn Fn(u32, *mut c_void)>,
+    ) -> Result<Subscription, VpxError>;
+}
+
+impl dyn VPXApi + '_ {
+    /// Broadcasts the request `R` with `data` as payload, receivers are allowed to fill it in.
+    ///
+    /// Receivers are called synchronously, so `data` holds their answers once this returns.
+    pub fn broadcast_with<R: Request>(&self, data: &mut R::Data) -> Result<(), VpxError> {
+        // receivers can write any bytes, which `MessagePayload` types are valid for
+        unsafe {
+            self.broadcast_msg_data(
+                self.endpoint_id(),
+                R::NAMESPACE,
+                R::NAME,
+                data as *mut R::Data as *mut c_void,
+            )
+        }
+    }
+
+    /// Reads a setting from VPinballX.ini and parses it, `None` if the key is missing.
+    ///
+    /// Plugin settings live in the `Plugin.<id>` section.
+    pub fn get_setting<T: FromStr>(
+        &self,
+        name_space: &str,
+        name: &str,
+    ) -> Result<Option<T>, SettingError>
+    where
+        T::Err: Display,
+    {
+        let Some(value) = self.get_setting_str(name_space, name)? else {
+            return Ok(None);
+        };
+        match value.trim().parse() {
+            Ok(parsed) => Ok(Some(parsed)),
+            Err(error) => Err(SettingError::Parse {
+                value,
+                error: error.to_string(),
+            }),
+        }
+    }
+
+    /// Broadcasts the request `R` initialized with its default data and returns the data as
+    /// filled in by the receivers.
+    pub fn query<R: Request>(&self) -> Result<R::Data, VpxError>
+    where
+        R::Data: Default,
+    {
+        let mut data = R::Data::default();
+        self.broadcast_with::<R>(&mut data)?;
+        Ok(data)
+    }
+
+    /// Registers an option with one label per variant of `E` and returns the selected one.
+    pub fn get_choice_option<E: OptionChoice>(
+        &self,
+        page_id: &str,
+        option_id: &str,
+        show_mask: u32,
+        option_name: &str,
+        default: E,
+    ) -> Result<E, VpxError> {
+        options::read_choice(
+            self,
+            &OptionDef {
+                page_id,
+                id: option_id,
+                name: option_name,
+                show_mask,
+                default,
+                min: None,
+                max: None,
+                step: None,
+                unit: bindings::OptionUnit::None,
+            },
+        )
+    }
+
+    /// Reads the options and keeps them up to date when the user changes settings.
+    pub fn options<T: PluginOptions + PartialEq + 'static>(&self) -> Result<Options<T>, VpxError> {
+        Options::new(self)
+    }
+
+    /// Every DMD the controller plugins can render right now, subscribe to
+    /// [`messages::DmdSourcesChanged`] to hear about new ones.
+    pub fn dmd_sources(&self) -> Result<Vec<DmdSource>, VpxError> {
+        dmd::dmd_sources(self)
+    }
+
+    /// Makes `T` available to table scripts, see [`ScriptClass`].
+    pub fn register_script_class<T: 'static>(
+        &self,
+        class: ScriptClass<T>,
+    ) -> Result<ScriptClassHandle, VpxError> {
+        self.register_script_class_definition(class.into_definition()?)
+    }
+
+    /// State that is created when a game starts and dropped when it ends.
+    pub fn game_session<S: GameState>(&self) -> Result<GameSession<S>, VpxError> {
+        GameSession::new(self)
+    }
+
+    /// Subscribes to a known message, the callback receives the decoded payload.
+    pub fn subscribe<M: Message>(
+        &self,
+        callback: impl for<'a> Fn(M::Payload<'a>) + 'static,
+    ) -> Result<Subscription, VpxError> {
+        self.subscribe_msg_data(
+            M::NAMESPACE,
+            M::NAME,
+            Box::new(move |msg_id, data| match unsafe { M::decode(data) } {
+                Some(payload) => callback(payload),
+                None => warn!(
+                    "Ignoring message {msg_id} ({}:{}) with invalid data",
+                    M::NAMESPACE,
+                    M::NAME
+                ),
+            }),
+        )
+    }
 }
 
 pub struct WrappedPluginApi {
     session_id: c_uint,
     msg: *mut bindings::MsgPluginAPI,
     vpx: *mut bindings::VPXPluginAPI,
-    callbacks: HashMap<u32, *mut c_void>,
+    capabilities: HostCapabilities,
+    labels: LabelStore,
+    notifier: Rc<Notifier>,
+    prerender: Rc<Prerender>,
+    scripting: Rc<Scripting>,
+    bus: Rc<MsgBus>,
+    scheduler: Rc<Scheduler>,
+    handle_shared: Arc<HandleShared>,
+    panic: Arc<PanicState>,
+    /// Whether our logs go to the host LoggingPlugin.
+    host_logging: bool,
+    /// `None` if we could not track game start and end, in-game checks are skipped then.
+    in_game: Option<Rc<Cell<bool>>>,
+    _game_tracking: Vec<Subscription>,
 }
 
 impl WrappedPluginApi {
     pub fn new(session_id: c_uint, msg: *mut bindings::MsgPluginAPI) -> Self {
+        let panic = Arc::new(PanicState::default());
+        let bus = Rc::new(MsgBus::new(session_id, msg, Arc::clone(&panic)));
+        let (in_game, game_tracking) = match Self::track_game(&bus) {
+            Ok((in_game, subscriptions)) => (Some(in_game), subscriptions),
+            Err(error) => {
+                warn!("Unable to track game start and end: {error}");
+                (None, Vec::new())
+            }
+        };
         Self {
             session_id,
             msg,
             vpx: std::ptr::null_mut(),
-            callbacks: HashMap::new(),
+            capabilities: HostCapabilities::detect(msg, std::ptr::null()),
+            labels: LabelStore::default(),
+            notifier: Rc::default(),
+            prerender: Rc::default(),
+            scripting: Rc::new(Scripting::new(Arc::clone(&panic))),
+            bus,
+            scheduler: Rc::new(Scheduler::new(msg, Arc::clone(&panic))),
+            handle_shared: Arc::new(HandleShared::new(msg, Arc::clone(&panic))),
+            panic,
+            host_logging: false,
+            in_game,
+            _game_tracking: game_tracking,
+        }
+    }
+
+    /// Subscribed before the plugin so its game start callbacks already see the game running,
+    /// game end is tracked last so its game end callbacks are still in the game.
+    fn track_game(bus: &Rc<MsgBus>) -> Result<(Rc<Cell<bool>>, Vec<Subscription>), VpxError> {
+        let in_game = Rc::new(Cell::new(false));
+        let started = Rc::clone(&in_game);
+        let start = bus.subscribe(
+            VPXPI_NAMESPACE,
+            VPXPI_EVT_ON_GAME_START,
+            Rc::new(move |_, _| started.set(true)),
+        )?;
+        let ended = Rc::clone(&in_game);
+        let end = bus.subscribe_last(
+            VPXPI_NAMESPACE,
+            VPXPI_EVT_ON_GAME_END,
+            Rc::new(move |_, _| ended.set(false)),
+        )?;
+        Ok((in_game, vec![start, end]))
+    }
+
+    fn set_vpx(&mut self, vpx: *mut bindings::VPXPluginAPI) {
+        self.vpx = vpx;
+        self.notifier.set_vpx(vpx);
+        self.prerender.set_vpx(vpx);
+    }
+
+    /// The ScriptablePlugin is optional, without it plugins can not register script classes.
+    fn attach_scripting(&mut self) {
+        let mut scripting: *mut bindings::ScriptablePluginAPI = std::ptr::null_mut();
+        let api: &dyn VPXApi = self;
+        if let Err(error) = api.broadcast_with::<messages::GetScriptableApi>(&mut scripting) {
+            warn!("Unable to get the scriptable api: {error}");
+        }
+        self.scripting.set_api(scripting);
+    }
+
+    fn vpx(&self) -> Result<&bindings::VPXPluginAPI, VpxError> {
+        if self.vpx.is_null() {
+            return Err(VpxError::ApiUnavailable);
+        }
+        Ok(unsafe { &*self.vpx })
+    }
+
+    fn require_game(&self) -> Result<(), VpxError> {
+        match &self.in_game {
+            Some(in_game) if !in_game.get() => Err(VpxError::NotInGame),
+            _ => Ok(()),
         }
     }
+
+    /// Gives back everything we registered with the host, safe to call more than once.
+    fn release_host_resources(&mut self) {
+        self.bus.unsubscribe_all();
+        self.scheduler.clear();
+        self.bus.ids.borrow_mut().release_all();
+        self.labels.clear();
+        self.prerender.release_all();
+        self.scripting.release_all();
+        self.scripting.set_api(std::ptr::null());
+        self.handle_shared.detach();
+        self.panic.set_notifier(std::ptr::null_mut());
+        self.set_vpx(std::ptr::null_mut());
+        if std::mem::take(&mut self.host_logging) {
+            logging::detach_host();
+        }
+        if let Some(in_game) = &self.in_game {
+            in_game.set(false);
+        }
+    }
+}
+
+impl Drop for WrappedPluginApi {
+    fn drop(&mut self) {
+        // the host must never call into callbacks we are about to free, even if loading panicked
+        self.release_host_resources();
+    }
 }
 
 pub struct PluginWrapper<P: Plugin> {
-    pub(crate) plugin: P,
-    api: WrappedPluginApi,
+    /// Shared with the lifecycle hooks, which are called from message callbacks.
+    plugin: Rc<RefCell<P>>,
+    _hooks: Vec<Subscription>,
+    // boxed so the address stays stable for the ApiHandle and the hooks
+    api: Box<WrappedPluginApi>,
 }
 
 impl<P: Plugin> PluginWrapper<P> {
     pub fn new(plugin: P, session_id: c_uint, msg: *mut bindings::MsgPluginAPI) -> Self {
         Self {
-            plugin,
-            api: WrappedPluginApi::new(session_id, msg),
+            plugin: Rc::new(RefCell::new(plugin)),
+            _hooks: Vec::new(),
+            api: Box::new(WrappedPluginApi::new(session_id, msg)),
         }
     }
 
-    pub fn load(&mut self) {
+    /// Calls `hook` on the plugin for every `msg_name` message.
+    fn hook(
+        &self,
+        msg_name: &'static str,
+        hook: fn(&mut P, &dyn VPXApi),
+    ) -> Result<Subscription, VpxError> {
+        let plugin = Rc::downgrade(&self.plugin);
+        let api: *const WrappedPluginApi = &*self.api;
+        self.api.bus.subscribe(
+            VPXPI_NAMESPACE,
+            msg_name,
+            Rc::new(move |_, _| {
+                let Some(plugin) = plugin.upgrade() else {
+                    return;
+                };
+                // the hooks are unsubscribed before the api is freed
+                let api = unsafe { &*api };
+                match plugin.try_borrow_mut() {
+                    Ok(mut plugin) => hook(&mut plugin, api),
+                    Err(_) => warn!("Skipping {msg_name} hook, the plugin is already handling one"),
+                };
+            }),
+        )
+    }
+
+    fn wire_hooks(&mut self) -> Result<(), VpxError> {
+        self._hooks = vec![
+            self.hook(VPXPI_EVT_ON_GAME_START, P::on_game_start)?,
+            self.hook(VPXPI_EVT_ON_GAME_END, P::on_game_end)?,
+            self.hook(VPXPI_EVT_ON_PREPARE_FRAME, P::on_prepare_frame)?,
+            self.hook(VPXPI_EVT_ON_SETTINGS_CHANGED, P::on_settings_changed)?,
+        ];
+        Ok(())
+    }
+
+    /// Fails without loading the plugin if the host does not meet the plugin requirements.
+    pub fn load(&mut self) -> Result<(), VpxError> {
         info!("load()");
-        let vpxpi_name_space: *const c_char = VPXPI_NAMESPACE.as_ptr() as *const c_char;
-        let vpxpi_get_api: *const c_char = VPXPI_MSG_GET_API.as_ptr() as *const c_char;
-        unsafe {
-            let msg_id = (*self.api.msg).GetMsgID.unwrap()(vpxpi_name_space, vpxpi_get_api);
-            // sends the pointer location of the vpx api to the plugin system for populating the vpx pointer
-            (*self.api.msg).BroadcastMsg.unwrap()(
-                self.api.session_id,
-                msg_id,
-                &mut self.api.vpx as *mut *mut bindings::VPXPluginAPI as *mut c_void,
-            );
+        // sends the pointer location of the vpx api to the plugin system for populating the vpx pointer
+        let mut vpx: *mut bindings::VPXPluginAPI = std::ptr::null_mut();
+        let api: &dyn VPXApi = &*self.api;
+        if let Err(error) = api.broadcast_with::<messages::GetVpxApi>(&mut vpx) {
+            warn!("Unable to get the VPX api: {error}");
+        }
+        self.api.set_vpx(vpx);
+        // the LoggingPlugin is optional, without it we keep logging to stderr
+        let mut logging: *mut bindings::LoggingPluginAPI = std::ptr::null_mut();
+        let api: &dyn VPXApi = &*self.api;
+        if api
+            .broadcast_with::<messages::GetLoggingApi>(&mut logging)
+            .is_ok()
+        {
+            self.api.host_logging = logging::attach_host(logging);
+        }
+        self.api.attach_scripting();
+        self.api.capabilities = HostCapabilities::detect(self.api.msg, vpx);
+        if let Err(error) = self
+            .api
+            .capabilities
+            .check(P::MIN_HOST_VERSION, P::REQUIRED_FUNCTIONS)
+        {
+            error!("Plugin not loaded: {error}");
+            let api: &dyn VPXApi = &*self.api;
+            // best effort, the host might not support notifications either
+            let _ = api.push_notification(&format!("Plugin not loaded: {error}"), 10000);
+            self.api.release_host_resources();
+            return Err(error);
+        }
+        if P::NOTIFY_ON_PANIC {
+            self.api.panic.set_notifier(vpx);
         }
-        self.plugin.on_load(&mut self.api);
+        let api_ptr: *mut WrappedPluginApi = &mut *self.api;
+        self.api.handle_shared.attach(api_ptr);
+        self.plugin.borrow_mut().on_load(&mut *self.api);
+        // after on_load so options and game sessions are up to date when the hooks run
+        if let Err(error) = self.wire_hooks() {
+            warn!("Game lifecycle hooks unavailable: {error}");
+        }
+        Ok(())
     }
 
     pub fn unload(&mut self) {
         info!("unload()");
-        self.plugin.on_unload();
-        // unsubscribe all events
-        for (event_id, callback) in self.api.callbacks.iter() {
-            unsafe {
-                info!("Unsubscribing for event_id {event_id}");
-                (*self.api.msg).UnsubscribeMsg.unwrap()(*event_id, Some(trampoline));
-                // free the callback
-                drop(Box::from_raw(*callback as *mut Box<dyn Fn(u32)>));
-            }
-        }
-        // TODO we have to call ReleaseMsgID for all messages we looked up with GetMsgID
-        // self.api.msg.ReleaseMsgID.unwrap()(...);
-        self.api.callbacks.clear();
-        self.api.vpx = std::ptr::null_mut();
+        self.plugin.borrow_mut().on_unload();
+        self._hooks.clear();
+        self.api.release_host_resources();
     }
 
+    /// The api is not thread safe, use [`VPXApi::handle`] to call it from other threads.
     pub fn get_api(&self) -> &dyn VPXApi {
-        &self.api
+        debug_assert!(
+            self.api.handle_shared.is_main_thread(),
+            "VPXApi used outside of the main thread, use an ApiHandle instead"
+        );
+        &*self.api
     }
 }
 
 impl VPXApi for WrappedPluginApi {
-    fn get_table_info(&self) -> TableInfo {
+    fn get_table_info(&self) -> Result<TableInfo, VpxError> {
         info!("get_table_info()");
-        unsafe {
-            // create a mutable pointer to a VPXPluginAPI_TableInfo
-            let mut table_info = bindings::VPXTableInfo {
-                path: std::ptr::null(),
-                tableWidth: 0.0,
-                tableHeight: 0.0,
-            };
-            (*self.vpx).GetTableInfo.unwrap()(&mut table_info);
-            let path = CStr::from_ptr(table_info.path)
-                .to_str()
-                .unwrap()
-                .to_string();
-            // TODO how long does this table_info.path live?
-            //   should we free it?
-            TableInfo {
-                path,
-                tableWidth: table_info.tableWidth,
-                tableHeight: table_info.tableHeight,
-            }
+        self.require_game()?;
+        let get_table_info = require(self.vpx()?.GetTableInfo, "GetTableInfo")?;
+        let mut table_info = bindings::VPXTableInfo {
+            path: std::ptr::null(),
+            tableWidth: 0.0,
+            tableHeight: 0.0,
+        };
+        unsafe { get_table_info(&mut table_info) };
+        if table_info.path.is_null() {
+            return Err(VpxError::NoData("table path"));
         }
+        // TODO how long does this table_info.path live?
+        //   should we free it?
+        let path = unsafe { CStr::from_ptr(table_info.path) }
+            .to_str()?
+            .to_string();
+        Ok(TableInfo {
+            path,
+            tableWidth: table_info.tableWidth,
+            tableHeight: table_info.tableHeight,
+        })
     }
 
     fn get_option(
@@ -173,20 +629,21 @@ impl VPXApi for WrappedPluginApi {
         unit: bindings::OptionUnit,
         // array of strings
         values: &[&str],
-    ) -> f32 {
+    ) -> Result<f32, VpxError> {
         info!("get_option({option_name})");
-        unsafe {
-            let page_id = CString::new(page_id).unwrap();
-            let option_id = CString::new(option_id).unwrap();
-            let option_name = CString::new(option_name).unwrap();
-            let raws = values
-                .iter()
-                .map(|s| CString::new(s.as_bytes()).unwrap().into_raw())
-                .collect::<Vec<_>>();
-            let values_ptr: *mut *const ::std::os::raw::c_char =
-                raws.as_ptr() as *mut *const ::std::os::raw::c_char;
-
-            (*self.vpx).GetOption.unwrap()(
+        let get_option = require(self.vpx()?.GetOption, "GetOption")?;
+        let page_id = CString::new(page_id)?;
+        let option_id = CString::new(option_id)?;
+        let option_name = CString::new(option_name)?;
+        // the host may keep the labels around, they stay alive until the plugin unloads
+        let values_ptr = if values.is_empty() {
+            std::ptr::null_mut()
+        } else {
+            self.labels.intern(values)?
+        };
+
+        Ok(unsafe {
+            get_option(
                 page_id.as_ptr(),
                 option_id.as_ptr(),
                 show_mask,
@@ -198,102 +655,145 @@ impl VPXApi for WrappedPluginApi {
                 unit.into(),
                 values_ptr,
             )
-        }
+        })
     }
 
-    fn push_notification(&self, message: &str, length_ms: u32) {
-        info!("push_notification({message}, {length_ms} ms)");
-        let message_c = CString::new(message).unwrap();
-        unsafe {
-            (*self.vpx).PushNotification.unwrap()(message_c.as_ptr(), length_ms);
-        }
+    fn push_notification(
+        &self,
+        message: &str,
+        length_ms: u32,
+    ) -> Result<NotificationHandle, VpxError> {
+        self.notifier.push(message, length_ms)
+    }
+
+    fn live_notification(&self, length: Duration, min_interval: Duration) -> LiveNotification {
+        LiveNotification::new(&self.notifier, &self.scheduler, length, min_interval)
+    }
+
+    fn get_msg_id(&self, msg_name_space: &str, msg_name: &str) -> Result<MsgId, VpxError> {
+        self.bus.msg_id(msg_name_space, msg_name)
     }
 
-    fn broadcast_msg(&self, endpoint_id: c_uint, msg_name_space: &str, msg_name: &str) {
+    fn endpoint_id(&self) -> c_uint {
+        self.session_id
+    }
+
+    fn capabilities(&self) -> HostCapabilities {
+        self.capabilities
+    }
+
+    unsafe fn broadcast_msg_data(
+        &self,
+        endpoint_id: c_uint,
+        msg_name_space: &str,
+        msg_name: &str,
+        data: *mut c_void,
+    ) -> Result<(), VpxError> {
         info!("broadcast_event({endpoint_id}, {msg_name_space}, {msg_name})");
-        let msg_name_space_c = CString::new(msg_name_space).unwrap();
-        let msg_name_c = CString::new(msg_name).unwrap();
-        let msg_id = unsafe {
-            (*self.msg).GetMsgID.unwrap()(msg_name_space_c.as_ptr(), msg_name_c.as_ptr())
-        };
+        let broadcast_msg = require(unsafe { (*self.msg).BroadcastMsg }, "BroadcastMsg")?;
+        // released again afterward unless something else holds the id
+        let msg_id = self.bus.msg_id(msg_name_space, msg_name)?;
         unsafe {
-            (*self.msg).BroadcastMsg.unwrap()(endpoint_id, msg_id, std::ptr::null_mut());
+            broadcast_msg(endpoint_id, msg_id.raw(), data);
         }
+        Ok(())
     }
 
-    fn get_active_view_setup(&self) -> bindings::VPXViewSetupDef {
+    fn get_active_view_setup(&self) -> Result<ViewSetup, VpxError> {
         info!("get_active_view_setup()");
-        unsafe {
-            // create a mutable pointer to a VPXPluginAPI_ViewSetupDef
-            let mut view_setup = bindings::VPXViewSetupDef {
-                viewMode: 0,
-                sceneScaleX: 0.0,
-                sceneScaleY: 0.0,
-                sceneScaleZ: 0.0,
-                viewX: 0.0,
-                viewY: 0.0,
-                viewZ: 0.0,
-                lookAt: 0.0,
-                viewportRotation: 0.0,
-                FOV: 0.0,
-                layback: 0.0,
-                viewHOfs: 0.0,
-                viewVOfs: 0.0,
-                windowTopZOfs: 0.0,
-                windowBottomZOfs: 0.0,
-                screenWidth: 0.0,
-                screenHeight: 0.0,
-                screenInclination: 0.0,
-                realToVirtualScale: 0.0,
-                interpupillaryDistance: 0.0,
-            };
-            (*self.vpx).GetActiveViewSetup.unwrap()(&mut view_setup);
-            view_setup
-        }
+        self.require_game()?;
+        let get_active_view_setup = require(self.vpx()?.GetActiveViewSetup, "GetActiveViewSetup")?;
+        let mut view_setup = bindings::VPXViewSetupDef::from(&ViewSetup::default());
+        unsafe { get_active_view_setup(&mut view_setup) };
+        Ok(ViewSetup::from(&view_setup))
     }
 
-    fn subscribe_msg(
-        &mut self,
+    fn set_active_view_setup(&self, setup: &ViewSetup) -> Result<(), VpxError> {
+        info!("set_active_view_setup()");
+        self.require_game()?;
+        setup.validate()?;
+        let set_active_view_setup = require(self.vpx()?.SetActiveViewSetup, "SetActiveViewSetup")?;
+        let mut view_setup = bindings::VPXViewSetupDef::from(setup);
+        unsafe { set_active_view_setup(&mut view_setup) };
+        Ok(())
+    }
+
+    fn disable_static_prerendering(&self) -> Result<StaticPrerenderGuard, VpxError> {
+        self.prerender.acquire()
+    }
+
+    fn register_script_class_definition(
+        &self,
+        class: ScriptClassDefinition,
+    ) -> Result<ScriptClassHandle, VpxError> {
+        self.scripting.register(class)
+    }
+
+    fn handle(&self) -> ApiHandle {
+        ApiHandle::new(Arc::clone(&self.handle_shared))
+    }
+
+    fn run_on_main_thread(
+        &self,
+        delay: Duration,
+        task: Box<dyn FnOnce()>,
+    ) -> Result<TaskHandle, VpxError> {
+        self.scheduler.run_once(delay, task)
+    }
+
+    fn timer(&self, interval: Duration, callback: Box<dyn FnMut()>) -> Result<Timer, VpxError> {
+        self.scheduler.repeat(interval, callback)
+    }
+
+    fn get_setting_str(
+        &self,
+        name_space: &str,
+        name: &str,
+    ) -> Result<Option<String>, SettingError> {
+        info!("get_setting({name_space}, {name})");
+        settings::read_setting(self.msg, name_space, name)
+    }
+
+    fn subscribe_msg_data(
+        &self,
         msg_name_space: &str,
         msg_name: &str,
-        callback_closure: Box<dyn Fn(u32)>,
-    ) {
+        callback_closure: Box<dyn Fn(u32, *mut c_void)>,
+    ) -> Result<Subscription, VpxError> {
         info!("subscribe_event({msg_name_space}, {msg_name})");
-        let msg_name_space_c = CString::new(msg_name_space).unwrap();
-        let msg_name_c = CString::new(msg_name).unwrap();
-        let message_id = unsafe {
-            (*self.msg).GetMsgID.unwrap()(msg_name_space_c.as_ptr(), msg_name_c.as_ptr())
-        };
-        // only allow one callback per event
-        assert!(
-            !self.callbacks.contains_key(&message_id),
-            "Event {msg_name} already subscribed"
-        );
-
-        // Wrap it again in a Box to keep it alive.
-        // Not sure why this is required, but otherwise we get 0x1 for trivial closures.
-        // see https://users.rust-lang.org/t/how-to-convert-box-dyn-fn-into-raw-pointer-and-then-call-it/104410/2
-        let wrapped = Box::new(callback_closure);
-        let user_data: *mut c_void = Box::into_raw(wrapped) as *mut c_void;
-        // can't be 0x1
-        assert_ne!(user_data as u64, 0x1, "Invalid user_data");
-        self.callbacks.insert(message_id, user_data);
-        info!("Plugin: Subscribing for event_id {message_id} with user_data {user_data:?}");
-        unsafe {
-            (*self.msg).SubscribeMsg.unwrap()(
-                self.session_id,
-                message_id,
-                Some(trampoline),
-                user_data,
-            );
-        }
+        self.bus
+            .subscribe(msg_name_space, msg_name, Rc::from(callback_closure))
     }
 }
 
-pub trait Plugin: Sized {
+pub trait Plugin: Sized + 'static {
+    /// Id of the plugin, keep in sync with `id` in plugin.cfg. Used to tell the logs of
+    /// plugins apart.
+    const ID: &'static str;
+    /// Show a notification to the user when the plugin gets disabled after a panic.
+    const NOTIFY_ON_PANIC: bool = true;
+    /// Loading fails on hosts with an older plugin api, keep in sync with `vpx_api` in
+    /// plugin.cfg. The version is derived from the host api tables, see
+    /// [`HostCapabilities::version`].
+    const MIN_HOST_VERSION: Option<HostVersion> = None;
+    /// Loading fails if the host does not provide one of these functions.
+    const REQUIRED_FUNCTIONS: &'static [HostFunction] = &[];
+
     fn new() -> Self;
     fn on_load(&mut self, api: &mut dyn VPXApi);
     fn on_unload(&mut self);
+
+    /// Game is starting, the in-game functions of the api can be used from now on.
+    fn on_game_start(&mut self, api: &dyn VPXApi) {}
+
+    /// Game is ending, the in-game functions still work until this returns.
+    fn on_game_end(&mut self, api: &dyn VPXApi) {}
+
+    /// Called before each frame is prepared for rendering, keep it short.
+    fn on_prepare_frame(&mut self, api: &dyn VPXApi) {}
+
+    /// The user changed settings, options should be read again.
+    fn on_settings_changed(&mut self, api: &dyn VPXApi) {}
 }
 
 pub struct VPXPlugin {
@@ -304,15 +804,7 @@ pub struct VPXPlugin {
     callbacks: HashMap<u32, *mut c_void>,
 }
 
-// https://adventures.michaelfbryan.com/posts/rust-closures-in-ffi/
-//
-unsafe extern "C" fn trampoline(event_id: c_uint, user_data: *mut c_void, _data: *mut c_void) {
-    //info!("Plugin: trampoline({event_id} {user_data:?})");
-    let user_data = &mut *(user_data as *mut Box<dyn Fn(u32)>);
-    user_data(event_id);
-}
-
-#[derive(Debug)]
+#[derive(Debug, Clone, Default)]
 pub struct TableInfo {
     pub path: String,
     pub tableWidth: f32,
@@ -339,48 +831,180 @@ macro_rules! plugin {
 
         // TODO is this a good idea, how can we keep track of the instance?
         /// Everything should be called from a single thread that originates on the vpinball side.
-        static mut PLUGIN: Option<std::rc::Rc<PluginWrapper<$plugin>>> = None;
+        static mut PLUGIN: Option<PluginWrapper<$plugin>> = None;
+
+        const _: () = assert!(
+            match <$plugin as vpinball_plugin_api::Plugin>::MIN_HOST_VERSION {
+                Some(version) => !version.newer_than(vpinball_plugin_api::HostVersion::LATEST),
+                None => true,
+            },
+            "MIN_HOST_VERSION is newer than any version the host can be detected as"
+        );
 
+        /// Api of the loaded plugin, panics if it is not loaded. The reference must not be kept
+        /// past `PluginUnload`, prefer [`get_plugin_api_handle`] for anything that outlives a call.
         pub fn get_plugin_api() -> &'static dyn VPXApi {
             unsafe {
                 match PLUGIN {
-                    Some(ref wrapper_rc) => wrapper_rc.get_api(),
+                    Some(ref wrapper) => wrapper.get_api(),
                     None => panic!("Plugin not loaded"),
                 }
             }
         }
 
-        #[no_mangle]
-        pub extern "C" fn PluginLoad(session_id: c_uint, msg: *mut MsgPluginAPI) {
-            // TODO how does this work with multiple plugins?
-            simple_logger::SimpleLogger::new().env().init().unwrap();
-            // fail if already loaded
-            assert!(unsafe { PLUGIN.is_none() }, "Plugin already loaded");
-            log::info!("PluginLoad()");
+        /// Handle to the api of the loaded plugin, `None` while it is not loaded. Calls through
+        /// the handle fail with `VpxError::Unloaded` once this instance unloads.
+        pub fn get_plugin_api_handle() -> Option<vpinball_plugin_api::ApiHandle> {
             unsafe {
-                let plugin = $plugin::new();
-                // create a wrapper around the plugin
-                let mut wrapper = PluginWrapper::new(plugin, session_id, msg);
-                wrapper.load();
-                PLUGIN = Some(std::rc::Rc::new(wrapper));
+                match PLUGIN {
+                    Some(ref wrapper) => Some(wrapper.get_api().handle()),
+                    None => None,
+                }
             }
         }
 
         #[no_mangle]
-        pub extern "C" fn PluginUnload() {
-            unsafe {
-                if let Some(wrapper_rc) = PLUGIN.take() {
-                    match std::rc::Rc::try_unwrap(wrapper_rc) {
-                        Ok(mut wrapper) => {
-                            log::info!("PluginUnload()");
-                            wrapper.unload();
-                        }
-                        Err(_) => {
-                            panic!("Failed to get mutable reference to plugin");
-                        }
+        pub extern "C" fn PluginLoad(session_id: c_uint, msg: *mut MsgPluginAPI) {
+            // a panic here must not unwind into vpinball, the plugin just stays unloaded
+            vpinball_plugin_api::catch_panic("PluginLoad", || {
+                vpinball_plugin_api::init_logging(env!("CARGO_CRATE_NAME"), $plugin::ID);
+                log::info!("PluginLoad()");
+                if unsafe { PLUGIN.is_some() } {
+                    log::warn!(
+                        "PluginLoad() without PluginUnload(), unloading the previous instance"
+                    );
+                    PluginUnload();
+                }
+                unsafe {
+                    let plugin = $plugin::new();
+                    // create a wrapper around the plugin
+                    let mut wrapper = PluginWrapper::new(plugin, session_id, msg);
+                    // the reason was already logged, the plugin just stays unloaded
+                    if wrapper.load().is_ok() {
+                        PLUGIN = Some(wrapper);
                     }
                 }
-            }
+            });
+        }
+
+        #[no_mangle]
+        pub extern "C" fn PluginUnload() {
+            vpinball_plugin_api::catch_panic("PluginUnload", || unsafe {
+                if let Some(mut wrapper) = PLUGIN.take() {
+                    log::info!("PluginUnload()");
+                    wrapper.unload();
+                }
+            });
         }
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::{GameEnd, GameStart, PrepareFrame};
+    use crate::test::{TestMsgPluginAPI, TestVPXPluginAPI, TEST_SESSION_ID};
+
+    /// Records every hook and whether in-game calls were allowed while it ran.
+    #[derive(Default)]
+    struct LifecyclePlugin {
+        events: Vec<(&'static str, bool)>,
+    }
+
+    fn in_game(api: &dyn VPXApi) -> bool {
+        api.get_table_info().err() != Some(VpxError::NotInGame)
+    }
+
+    impl Plugin for LifecyclePlugin {
+        const ID: &'static str = "lifecycle";
+        fn new() -> Self {
+            Self::default()
+        }
+
+        fn on_load(&mut self, _api: &mut dyn VPXApi) {}
+
+        fn on_unload(&mut self) {}
+
+        fn on_game_start(&mut self, api: &dyn VPXApi) {
+            self.events.push(("start", in_game(api)));
+        }
+
+        fn on_game_end(&mut self, api: &dyn VPXApi) {
+            self.events.push(("end", in_game(api)));
+        }
+
+        fn on_prepare_frame(&mut self, api: &dyn VPXApi) {
+            self.events.push(("frame", in_game(api)));
+        }
+    }
+
+    #[test]
+    fn test_lifecycle_hooks() {
+        let vpx_api = TestVPXPluginAPI::init();
+        let mut msg_api = TestMsgPluginAPI::init(&vpx_api);
+        let mut wrapper = PluginWrapper::new(LifecyclePlugin::new(), TEST_SESSION_ID, &mut msg_api);
+        wrapper.load().unwrap();
+
+        let api = wrapper.get_api();
+        for (namespace, name) in [
+            (GameStart::NAMESPACE, GameStart::NAME),
+            (PrepareFrame::NAMESPACE, PrepareFrame::NAME),
+            (GameEnd::NAMESPACE, GameEnd::NAME),
+        ] {
+            let msg_id = api.get_msg_id(namespace, name).unwrap();
+            TestMsgPluginAPI::broadcast(msg_id.raw());
+        }
+        assert_eq!(
+            wrapper.plugin.borrow().events,
+            vec![("start", true), ("frame", true), ("end", true)]
+        );
+        assert!(!in_game(api));
+        let game_start = api
+            .get_msg_id(GameStart::NAMESPACE, GameStart::NAME)
+            .unwrap()
+            .raw();
+        assert_eq!(TestMsgPluginAPI::subscriptions(game_start), 1);
+
+        wrapper.unload();
+        assert_eq!(
+            TestMsgPluginAPI::subscriptions(game_start),
+            0,
+            "{} hook left behind",
+            GameStart::NAME
+        );
+    }
+
+    #[derive(Default)]
+    struct RecentPlugin {
+        loaded: bool,
+    }
+
+    impl Plugin for RecentPlugin {
+        const ID: &'static str = "recent";
+        const MIN_HOST_VERSION: Option<HostVersion> = Some(HostVersion::new(10, 8, 1));
+
+        fn new() -> Self {
+            Self::default()
+        }
+
+        fn on_load(&mut self, _api: &mut dyn VPXApi) {
+            self.loaded = true;
+        }
+
+        fn on_unload(&mut self) {}
+    }
+
+    #[test]
+    fn test_min_host_version() {
+        // the test host leaves out functions every 10.8.1 host provides
+        let vpx_api = TestVPXPluginAPI::init();
+        let mut msg_api = TestMsgPluginAPI::init(&vpx_api);
+        let mut wrapper = PluginWrapper::new(RecentPlugin::new(), TEST_SESSION_ID, &mut msg_api);
+        let error = wrapper.load().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "plugin requires VPX plugin api 10.8.1 or newer, host provides an older one"
+        );
+        assert!(!wrapper.plugin.borrow().loaded);
+    }
+}
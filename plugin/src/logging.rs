@@ -0,0 +1,214 @@
+use crate::bindings;
+use log::{debug, Level, Log, Metadata, Record};
+use simple_logger::SimpleLogger;
+use std::ffi::{c_char, c_uint, CString};
+use std::sync::{Mutex, Once, PoisonError};
+
+/// Crate name and plugin id of every plugin that initialised logging.
+///
+/// Every plugin library is a cdylib with its own copy of these statics, so the logger is only
+/// shared by plugin crates linked into the same library and by reloads of that library, not
+/// between separately built plugins.
+static PLUGINS: Mutex<Vec<(&'static str, &'static str)>> = Mutex::new(Vec::new());
+
+static INSTALL: Once = Once::new();
+
+type HostLog = unsafe extern "C" fn(level: c_uint, message: *const c_char);
+
+/// `Log` of the host LoggingPlugin and how many loaded plugins attached to it, only counts the
+/// plugins of this library, see [`PLUGINS`].
+static HOST: Mutex<Option<(HostLog, usize)>> = Mutex::new(None);
+
+/// Forwards records to the host log when it provides one, to stderr otherwise. Targets of
+/// plugin crates are shown with the plugin id instead of the crate name.
+struct PluginLogger {
+    inner: SimpleLogger,
+}
+
+impl Log for PluginLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let host = HOST
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .map(|(log, _)| log);
+        if let Some(host_log) = host {
+            let message = match plugin_id(record.target()) {
+                Some(id) => format!("[{id}] {}", record.args()),
+                None => format!("[{}] {}", record.target(), record.args()),
+            };
+            // the host can not show a nul, drop them instead of the whole record
+            let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+            unsafe { host_log(host_level(record.level()), message.as_ptr()) };
+            return;
+        }
+        match scoped_target(record.target()) {
+            Some(target) => self.inner.log(
+                &Record::builder()
+                    .level(record.level())
+                    .target(&target)
+                    .args(*record.args())
+                    .module_path(record.module_path())
+                    .file(record.file())
+                    .line(record.line())
+                    .build(),
+            ),
+            None => self.inner.log(record),
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// `vpinball_plugin_fps::fpscounter` becomes `fps::fpscounter`, targets of other crates are
+/// kept as they are.
+fn scoped_target(target: &str) -> Option<String> {
+    let plugins = PLUGINS.lock().unwrap_or_else(PoisonError::into_inner);
+    plugins.iter().find_map(|(crate_name, id)| {
+        let rest = target.strip_prefix(crate_name)?;
+        (rest.is_empty() || rest.starts_with("::")).then(|| format!("{id}{rest}"))
+    })
+}
+
+/// The plugin a record belongs to, records of this crate belong to the plugin if there is
+/// only one.
+fn plugin_id(target: &str) -> Option<&'static str> {
+    let plugins = PLUGINS.lock().unwrap_or_else(PoisonError::into_inner);
+    let owner = plugins.iter().find(|(crate_name, _)| {
+        target
+            .strip_prefix(crate_name)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+    });
+    match (owner, plugins.as_slice()) {
+        (Some((_, id)), _) => Some(id),
+        (None, [(_, id)]) => Some(id),
+        _ => None,
+    }
+}
+
+fn host_level(level: Level) -> c_uint {
+    match level {
+        Level::Error => bindings::LPI_LVL_ERROR,
+        Level::Warn => bindings::LPI_LVL_WARN,
+        Level::Info => bindings::LPI_LVL_INFO,
+        Level::Debug | Level::Trace => bindings::LPI_LVL_DEBUG,
+    }
+}
+
+/// Sends logs to the host from now on, returns `false` if the host has no usable log
+/// function. Every successful attach needs a [`detach_host`].
+pub(crate) fn attach_host(api: *const bindings::LoggingPluginAPI) -> bool {
+    let Some(log) = (unsafe { api.as_ref() }).and_then(|api| api.Log) else {
+        return false;
+    };
+    let mut host = HOST.lock().unwrap_or_else(PoisonError::into_inner);
+    let users = host.map_or(0, |(_, users)| users);
+    *host = Some((log, users + 1));
+    true
+}
+
+/// Goes back to stderr once the last plugin that attached is unloaded.
+pub(crate) fn detach_host() {
+    let mut host = HOST.lock().unwrap_or_else(PoisonError::into_inner);
+    *host = match *host {
+        Some((log, users)) if users > 1 => Some((log, users - 1)),
+        _ => None,
+    };
+}
+
+/// Installs the logger, or keeps the one that is already installed by an earlier load of this
+/// library or another plugin crate linked into it. The log level is read from `RUST_LOG`.
+///
+/// Called by the `plugin!` macro, `crate_name` is the crate of the plugin.
+pub fn init_logging(crate_name: &'static str, plugin_id: &'static str) {
+    {
+        let mut plugins = PLUGINS.lock().unwrap_or_else(PoisonError::into_inner);
+        if !plugins.contains(&(crate_name, plugin_id)) {
+            plugins.push((crate_name, plugin_id));
+        }
+    }
+    let mut installed = false;
+    INSTALL.call_once(|| {
+        let inner = SimpleLogger::new().env();
+        let max_level = inner.max_level();
+        installed = log::set_boxed_logger(Box::new(PluginLogger { inner })).is_ok();
+        if installed {
+            log::set_max_level(max_level);
+        }
+    });
+    if !installed {
+        debug!("Logger already installed, {plugin_id} logs through it");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::GetLoggingApi;
+    use crate::test::{wrapped_api, TestMsgPluginAPI};
+    use crate::VPXApi;
+
+    /// The logger state is process wide, tests that change it must not run in parallel.
+    static LOGGER_STATE: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn test_scoped_target() {
+        let _serial = LOGGER_STATE.lock().unwrap_or_else(PoisonError::into_inner);
+        init_logging("vpinball_plugin_test_a", "a");
+        init_logging("vpinball_plugin_test_a", "a");
+        init_logging("vpinball_plugin_test_b", "b");
+        assert_eq!(
+            scoped_target("vpinball_plugin_test_a"),
+            Some("a".to_string())
+        );
+        assert_eq!(
+            scoped_target("vpinball_plugin_test_b::camera"),
+            Some("b::camera".to_string())
+        );
+        assert_eq!(scoped_target("vpinball_plugin_test_ab"), None);
+        assert_eq!(scoped_target("vpinball_plugin_api::msg"), None);
+        let registered = PLUGINS.lock().unwrap();
+        assert_eq!(
+            registered
+                .iter()
+                .filter(|(crate_name, _)| crate_name.starts_with("vpinball_plugin_test"))
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_forward_to_host() {
+        let _serial = LOGGER_STATE.lock().unwrap_or_else(PoisonError::into_inner);
+        init_logging("vpinball_plugin_hosted", "host");
+        let api = wrapped_api();
+        let api: &dyn VPXApi = &*api;
+
+        let mut logging: *mut bindings::LoggingPluginAPI = std::ptr::null_mut();
+        api.broadcast_with::<GetLoggingApi>(&mut logging).unwrap();
+        assert!(!attach_host(logging));
+
+        TestMsgPluginAPI::enable_logging();
+        api.broadcast_with::<GetLoggingApi>(&mut logging).unwrap();
+        assert!(attach_host(logging));
+        log::warn!(target: "vpinball_plugin_hosted::camera", "forwarded to host");
+        detach_host();
+        log::warn!(target: "vpinball_plugin_hosted", "forwarded to stderr");
+
+        assert_eq!(
+            TestMsgPluginAPI::host_logs("forwarded to"),
+            vec![(
+                bindings::LPI_LVL_WARN,
+                "[host] forwarded to host".to_string()
+            )]
+        );
+    }
+}
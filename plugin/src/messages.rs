@@ -0,0 +1,258 @@
+//! Known host messages and how to decode their payload.
+
+use crate::bindings::{self, GetDmdSrcMsg};
+use crate::{
+    CTLPI_GETDMD_SRC_MSG, CTLPI_NAMESPACE, CTLPI_ONDMD_SRC_CHANGED_MSG, LPI_MSG_GET_API,
+    LPI_NAMESPACE, PMPI_EVT_ON_GAME_END, PMPI_EVT_ON_GAME_START, PMPI_NAMESPACE,
+    SCRIPTPI_MSG_GET_API, SCRIPTPI_NAMESPACE, VPXPI_EVT_ON_GAME_END, VPXPI_EVT_ON_GAME_START,
+    VPXPI_EVT_ON_PREPARE_FRAME, VPXPI_EVT_ON_SETTINGS_CHANGED, VPXPI_MSG_GET_API, VPXPI_NAMESPACE,
+};
+use std::ffi::{c_void, CStr};
+
+/// A message on the plugin message bus together with the type of its payload.
+///
+/// The payload borrows from the `data` pointer the host passes along with the message,
+/// so it is only valid for the duration of the callback.
+pub trait Message {
+    const NAMESPACE: &'static str;
+    const NAME: &'static str;
+
+    type Payload<'a>;
+
+    /// Decodes the message data, returns `None` if the data is missing or malformed.
+    ///
+    /// # Safety
+    ///
+    /// `data` must be the pointer that was broadcast together with this message.
+    unsafe fn decode<'a>(data: *mut c_void) -> Option<Self::Payload<'a>>;
+}
+
+/// Data that can be broadcast to other endpoints, which are free to write into it.
+///
+/// # Safety
+///
+/// The type must be `#[repr(C)]` and valid for any bit pattern, so no `bool`, enums,
+/// references or owning pointers. Raw pointers are fine.
+pub unsafe trait MessagePayload {}
+
+macro_rules! message_payload {
+    ($($ty:ty),*) => {
+        $(unsafe impl MessagePayload for $ty {})*
+    };
+}
+
+message_payload!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64, GetDmdSrcMsg);
+
+unsafe impl<T> MessagePayload for *mut T {}
+unsafe impl<T> MessagePayload for *const T {}
+unsafe impl<T: MessagePayload, const N: usize> MessagePayload for [T; N] {}
+
+/// A message that is broadcast with data for the receivers to fill in, see
+/// `api.broadcast_with::<R>()` and `api.query::<R>()`.
+pub trait Request: Message {
+    type Data: MessagePayload;
+}
+
+macro_rules! request {
+    ($(#[$doc:meta])* $name:ident, $name_space:expr, $msg:expr, $data:ty) => {
+        $(#[$doc])*
+        pub struct $name;
+
+        impl Message for $name {
+            const NAMESPACE: &'static str = $name_space;
+            const NAME: &'static str = $msg;
+
+            type Payload<'a> = &'a mut $data;
+
+            unsafe fn decode<'a>(data: *mut c_void) -> Option<Self::Payload<'a>> {
+                (data as *mut $data).as_mut()
+            }
+        }
+
+        impl Request for $name {
+            type Data = $data;
+        }
+    };
+}
+
+request!(
+    /// Asks the host for the VPX plugin api
+    GetVpxApi,
+    VPXPI_NAMESPACE,
+    VPXPI_MSG_GET_API,
+    *mut bindings::VPXPluginAPI
+);
+request!(
+    /// Asks the LoggingPlugin for its api, stays null without one
+    GetLoggingApi,
+    LPI_NAMESPACE,
+    LPI_MSG_GET_API,
+    *mut bindings::LoggingPluginAPI
+);
+request!(
+    /// Asks the ScriptablePlugin for its api, stays null without one
+    GetScriptableApi,
+    SCRIPTPI_NAMESPACE,
+    SCRIPTPI_MSG_GET_API,
+    *mut bindings::ScriptablePluginAPI
+);
+request!(
+    /// Asks the controller plugins for their DMDs, see `api.dmd_sources()`
+    GetDmdSources,
+    CTLPI_NAMESPACE,
+    CTLPI_GETDMD_SRC_MSG,
+    GetDmdSrcMsg
+);
+
+macro_rules! signal {
+    ($(#[$doc:meta])* $name:ident, $name_space:expr, $msg:expr) => {
+        $(#[$doc])*
+        pub struct $name;
+
+        impl Message for $name {
+            const NAMESPACE: &'static str = $name_space;
+            const NAME: &'static str = $msg;
+
+            type Payload<'a> = ();
+
+            unsafe fn decode<'a>(_data: *mut c_void) -> Option<Self::Payload<'a>> {
+                Some(())
+            }
+        }
+    };
+}
+
+signal!(
+    /// Game is starting, after this all functions of the API marked as 'in game only' can be called.
+    GameStart,
+    VPXPI_NAMESPACE,
+    VPXPI_EVT_ON_GAME_START
+);
+signal!(
+    /// Game is ending
+    GameEnd,
+    VPXPI_NAMESPACE,
+    VPXPI_EVT_ON_GAME_END
+);
+signal!(
+    /// Sent before each frame is prepared for rendering
+    PrepareFrame,
+    VPXPI_NAMESPACE,
+    VPXPI_EVT_ON_PREPARE_FRAME
+);
+signal!(
+    /// User changed settings, options should be read again
+    SettingsChanged,
+    VPXPI_NAMESPACE,
+    VPXPI_EVT_ON_SETTINGS_CHANGED
+);
+signal!(
+    /// PinMAME stopped emulating a game
+    PinMameGameEnd,
+    PMPI_NAMESPACE,
+    PMPI_EVT_ON_GAME_END
+);
+signal!(
+    /// A controller plugin added or removed a DMD, see `api.dmd_sources()`
+    DmdSourcesChanged,
+    CTLPI_NAMESPACE,
+    CTLPI_ONDMD_SRC_CHANGED_MSG
+);
+
+/// PinMAME started emulating a game
+pub struct PinMameGameStart;
+
+#[derive(Debug)]
+pub struct GameStartInfo<'a> {
+    /// Name of the rom that is being emulated
+    pub rom: &'a str,
+}
+
+impl Message for PinMameGameStart {
+    const NAMESPACE: &'static str = PMPI_NAMESPACE;
+    const NAME: &'static str = PMPI_EVT_ON_GAME_START;
+
+    type Payload<'a> = GameStartInfo<'a>;
+
+    unsafe fn decode<'a>(data: *mut c_void) -> Option<Self::Payload<'a>> {
+        if data.is_null() {
+            return None;
+        }
+        let rom = CStr::from_ptr(data as *const std::ffi::c_char)
+            .to_str()
+            .ok()?;
+        Some(GameStartInfo { rom })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{wrapped_api, TestMsgPluginAPI};
+    use crate::VPXApi;
+    use std::cell::RefCell;
+    use std::ffi::CString;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_typed_payload() {
+        let api = wrapped_api();
+        let api: &dyn VPXApi = &*api;
+
+        let roms = Rc::new(RefCell::new(Vec::new()));
+        let roms_clone = Rc::clone(&roms);
+        let subscription = api
+            .subscribe::<PinMameGameStart>(move |info| {
+                roms_clone.borrow_mut().push(info.rom.to_string());
+            })
+            .unwrap();
+
+        let rom = CString::new("tz_94h").unwrap();
+        let msg_id = subscription.msg_id().raw();
+        unsafe {
+            TestMsgPluginAPI::dispatch(msg_id, rom.as_ptr() as *mut c_void);
+        }
+        // missing data is not passed on to the callback
+        TestMsgPluginAPI::broadcast(msg_id);
+
+        assert_eq!(*roms.borrow(), vec!["tz_94h".to_string()]);
+    }
+
+    #[derive(Default)]
+    #[repr(C)]
+    struct Answer {
+        value: u32,
+    }
+
+    unsafe impl MessagePayload for Answer {}
+
+    struct GetAnswer;
+
+    impl Message for GetAnswer {
+        const NAMESPACE: &'static str = "Test";
+        const NAME: &'static str = "GetAnswer";
+
+        type Payload<'a> = &'a mut Answer;
+
+        unsafe fn decode<'a>(data: *mut c_void) -> Option<Self::Payload<'a>> {
+            (data as *mut Answer).as_mut()
+        }
+    }
+
+    impl Request for GetAnswer {
+        type Data = Answer;
+    }
+
+    #[test]
+    fn test_query() {
+        let api = wrapped_api();
+        let api: &dyn VPXApi = &*api;
+
+        let _responder = api
+            .subscribe::<GetAnswer>(|answer| answer.value = 42)
+            .unwrap();
+
+        let answer = api.query::<GetAnswer>().unwrap();
+        assert_eq!(answer.value, 42);
+    }
+}
@@ -0,0 +1,435 @@
+use crate::bindings;
+use crate::error::{require, VpxError};
+use crate::panic::PanicState;
+use log::{debug, info, warn};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ffi::{c_uint, c_void, CString};
+use std::fmt::{Display, Formatter};
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+
+/// Message id as handed out by the host through `GetMsgID`.
+///
+/// The id is held by the [`MsgRegistry`] of the plugin as long as a handle or a
+/// [`Subscription`] for the message exists, the registry releases it through `ReleaseMsgID`
+/// once the last one is dropped or the plugin unloads. Cloning takes another reference.
+#[derive(Debug)]
+pub struct MsgId {
+    id: c_uint,
+    registry: Weak<RefCell<MsgRegistry>>,
+}
+
+impl MsgId {
+    pub fn raw(&self) -> c_uint {
+        self.id
+    }
+}
+
+impl Clone for MsgId {
+    fn clone(&self) -> Self {
+        if let Some(registry) = self.registry.upgrade() {
+            registry.borrow_mut().retain(self.id);
+        }
+        Self {
+            id: self.id,
+            registry: Weak::clone(&self.registry),
+        }
+    }
+}
+
+impl Drop for MsgId {
+    fn drop(&mut self) {
+        if let Some(registry) = self.registry.upgrade() {
+            registry.borrow_mut().release(self.id);
+        }
+    }
+}
+
+impl PartialEq for MsgId {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for MsgId {}
+
+impl Display for MsgId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.id)
+    }
+}
+
+impl PartialEq<c_uint> for MsgId {
+    fn eq(&self, other: &c_uint) -> bool {
+        self.id == *other
+    }
+}
+
+struct Entry {
+    id: c_uint,
+    refs: usize,
+}
+
+/// Caches namespace/name to id lookups and keeps track of how many times each id is in use.
+///
+/// The host expects every `GetMsgID` to be balanced by a `ReleaseMsgID`, we only call
+/// `GetMsgID` once per message and release it when the last reference goes away or
+/// when the plugin unloads.
+pub(crate) struct MsgRegistry {
+    msg: *mut bindings::MsgPluginAPI,
+    entries: HashMap<(String, String), Entry>,
+}
+
+impl MsgRegistry {
+    pub(crate) fn new(msg: *mut bindings::MsgPluginAPI) -> Self {
+        Self {
+            msg,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// Looks up the id and takes a reference on it, balance with [`MsgRegistry::release`].
+    pub(crate) fn acquire(&mut self, name_space: &str, name: &str) -> Result<c_uint, VpxError> {
+        let key = (name_space.to_string(), name.to_string());
+        if let Some(entry) = self.entries.get_mut(&key) {
+            entry.refs += 1;
+            return Ok(entry.id);
+        }
+        let name_space_c = CString::new(name_space)?;
+        let name_c = CString::new(name)?;
+        let get_msg_id = require(unsafe { (*self.msg).GetMsgID }, "GetMsgID")?;
+        let id = unsafe { get_msg_id(name_space_c.as_ptr(), name_c.as_ptr()) };
+        debug!("Acquired message id {id} for {name_space}:{name}");
+        self.entries.insert(key, Entry { id, refs: 1 });
+        Ok(id)
+    }
+
+    /// Takes another reference on an id that is already held.
+    fn retain(&mut self, id: c_uint) {
+        // ids are gone after release_all, releasing the clone later does nothing either
+        if let Some(entry) = self.entries.values_mut().find(|entry| entry.id == id) {
+            entry.refs += 1;
+        }
+    }
+
+    /// Drops a reference, the id is released on the host once no references are left.
+    pub(crate) fn release(&mut self, id: c_uint) {
+        let Some(key) = self
+            .entries
+            .iter()
+            .find(|(_, entry)| entry.id == id)
+            .map(|(key, _)| key.clone())
+        else {
+            debug!("Message id {id} was already released on unload");
+            return;
+        };
+        let entry = self.entries.get_mut(&key).unwrap();
+        entry.refs -= 1;
+        if entry.refs == 0 {
+            self.entries.remove(&key);
+            self.release_on_host(id);
+        }
+    }
+
+    /// Releases every id still held, used when the plugin unloads.
+    pub(crate) fn release_all(&mut self) {
+        for (_, entry) in std::mem::take(&mut self.entries) {
+            self.release_on_host(entry.id);
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn release_on_host(&self, id: c_uint) {
+        debug!("Releasing message id {id}");
+        match unsafe { (*self.msg).ReleaseMsgID } {
+            Some(release_msg_id) => unsafe { release_msg_id(id) },
+            None => warn!("Host does not provide ReleaseMsgID, leaking message id {id}"),
+        }
+    }
+}
+
+/// Called with the message id and the data pointer the message was broadcast with.
+pub(crate) type Callback = Rc<dyn Fn(u32, *mut c_void)>;
+
+/// Fans a single host subscription out to all closures registered for the same message.
+struct Dispatcher {
+    /// Token, callback and whether it has to run after all other callbacks
+    callbacks: RefCell<Vec<(usize, Callback, bool)>>,
+    panic: Arc<PanicState>,
+}
+
+/// Message bus state shared between the api and the [`Subscription`] guards it hands out.
+pub(crate) struct MsgBus {
+    session_id: c_uint,
+    msg: *mut bindings::MsgPluginAPI,
+    /// Shared with the [`MsgId`] handles, which release their reference when dropped.
+    pub(crate) ids: Rc<RefCell<MsgRegistry>>,
+    dispatchers: RefCell<HashMap<c_uint, Rc<Dispatcher>>>,
+    next_token: Cell<usize>,
+    panic: Arc<PanicState>,
+}
+
+impl MsgBus {
+    pub(crate) fn new(
+        session_id: c_uint,
+        msg: *mut bindings::MsgPluginAPI,
+        panic: Arc<PanicState>,
+    ) -> Self {
+        Self {
+            session_id,
+            msg,
+            ids: Rc::new(RefCell::new(MsgRegistry::new(msg))),
+            dispatchers: RefCell::new(HashMap::new()),
+            next_token: Cell::new(0),
+            panic,
+        }
+    }
+
+    /// Looks up the id of a message, it stays valid while the handle lives.
+    pub(crate) fn msg_id(&self, msg_name_space: &str, msg_name: &str) -> Result<MsgId, VpxError> {
+        let id = self.ids.borrow_mut().acquire(msg_name_space, msg_name)?;
+        Ok(MsgId {
+            id,
+            registry: Rc::downgrade(&self.ids),
+        })
+    }
+
+    pub(crate) fn subscribe(
+        self: &Rc<Self>,
+        msg_name_space: &str,
+        msg_name: &str,
+        callback: Callback,
+    ) -> Result<Subscription, VpxError> {
+        self.subscribe_ordered(msg_name_space, msg_name, callback, false)
+    }
+
+    /// Like [`MsgBus::subscribe`] but the callback runs after all other callbacks of the message.
+    pub(crate) fn subscribe_last(
+        self: &Rc<Self>,
+        msg_name_space: &str,
+        msg_name: &str,
+        callback: Callback,
+    ) -> Result<Subscription, VpxError> {
+        self.subscribe_ordered(msg_name_space, msg_name, callback, true)
+    }
+
+    fn subscribe_ordered(
+        self: &Rc<Self>,
+        msg_name_space: &str,
+        msg_name: &str,
+        callback: Callback,
+        last: bool,
+    ) -> Result<Subscription, VpxError> {
+        let subscribe_msg = require(unsafe { (*self.msg).SubscribeMsg }, "SubscribeMsg")?;
+        // every subscription holds its own reference on the id
+        let msg_id = self.msg_id(msg_name_space, msg_name)?;
+        let token = self.next_token.get();
+        self.next_token.set(token + 1);
+
+        let mut dispatchers = self.dispatchers.borrow_mut();
+        let dispatcher = dispatchers.entry(msg_id.raw()).or_insert_with(|| {
+            let dispatcher = Rc::new(Dispatcher {
+                callbacks: RefCell::new(Vec::new()),
+                panic: Arc::clone(&self.panic),
+            });
+            let user_data = Rc::as_ptr(&dispatcher) as *mut c_void;
+            info!("Plugin: Subscribing for event_id {msg_id} with user_data {user_data:?}");
+            unsafe {
+                subscribe_msg(self.session_id, msg_id.raw(), Some(trampoline), user_data);
+            }
+            dispatcher
+        });
+        let mut callbacks = dispatcher.callbacks.borrow_mut();
+        let index = if last {
+            callbacks.len()
+        } else {
+            callbacks
+                .iter()
+                .position(|(_, _, last)| *last)
+                .unwrap_or(callbacks.len())
+        };
+        callbacks.insert(index, (token, callback, last));
+
+        Ok(Subscription {
+            bus: Rc::downgrade(self),
+            msg_id,
+            token,
+        })
+    }
+
+    fn unsubscribe(&self, msg_id: c_uint, token: usize) {
+        let mut dispatchers = self.dispatchers.borrow_mut();
+        let Some(dispatcher) = dispatchers.get(&msg_id) else {
+            // already cleaned up by unsubscribe_all
+            return;
+        };
+        let now_empty = {
+            let mut callbacks = dispatcher.callbacks.borrow_mut();
+            let Some(index) = callbacks.iter().position(|(t, _, _)| *t == token) else {
+                return;
+            };
+            callbacks.remove(index);
+            callbacks.is_empty()
+        };
+        if now_empty {
+            self.unsubscribe_on_host(msg_id);
+            dispatchers.remove(&msg_id);
+        }
+    }
+
+    /// Removes every host subscription, outstanding [`Subscription`] guards become no-ops.
+    pub(crate) fn unsubscribe_all(&self) {
+        for (msg_id, _) in self.dispatchers.take() {
+            self.unsubscribe_on_host(msg_id);
+        }
+    }
+
+    fn unsubscribe_on_host(&self, msg_id: c_uint) {
+        info!("Unsubscribing for event_id {msg_id}");
+        match unsafe { (*self.msg).UnsubscribeMsg } {
+            Some(unsubscribe_msg) => unsafe { unsubscribe_msg(msg_id, Some(trampoline)) },
+            // nothing we can do, the dispatcher is about to be freed
+            None => warn!("Host does not provide UnsubscribeMsg for event_id {msg_id}"),
+        }
+    }
+
+    pub(crate) fn subscriber_count(&self, msg_id: c_uint) -> usize {
+        self.dispatchers
+            .borrow()
+            .get(&msg_id)
+            .map_or(0, |d| d.callbacks.borrow().len())
+    }
+}
+
+/// Keeps a message callback registered, dropping it unsubscribes the callback.
+///
+/// Any number of subscriptions can exist for the same message, the host subscription is
+/// removed once the last one is dropped. Subscriptions that outlive the plugin are
+/// cleaned up on unload and dropping them afterward does nothing.
+#[must_use = "dropping a Subscription immediately unsubscribes the callback"]
+pub struct Subscription {
+    bus: Weak<MsgBus>,
+    /// Holds the reference of the subscription, released after unsubscribing.
+    msg_id: MsgId,
+    token: usize,
+}
+
+impl Subscription {
+    pub fn msg_id(&self) -> &MsgId {
+        &self.msg_id
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        if let Some(bus) = self.bus.upgrade() {
+            bus.unsubscribe(self.msg_id.raw(), self.token);
+        }
+    }
+}
+
+// https://adventures.michaelfbryan.com/posts/rust-closures-in-ffi/
+//
+unsafe extern "C" fn trampoline(event_id: c_uint, user_data: *mut c_void, data: *mut c_void) {
+    //info!("Plugin: trampoline({event_id} {user_data:?})");
+    // keep the dispatcher alive even if a callback unsubscribes the last subscription
+    let user_data = user_data as *const Dispatcher;
+    Rc::increment_strong_count(user_data);
+    let dispatcher = Rc::from_raw(user_data);
+    dispatcher.panic.guard("message callback", || {
+        // callbacks are allowed to (un)subscribe, so don't hold the borrow while calling them
+        let callbacks: Vec<Callback> = dispatcher
+            .callbacks
+            .borrow()
+            .iter()
+            .map(|(_, callback, _)| Rc::clone(callback))
+            .collect();
+        for callback in callbacks {
+            callback(event_id, data);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{TestMsgPluginAPI, TestVPXPluginAPI, TEST_SESSION_ID};
+    use crate::{VPXPI_EVT_ON_GAME_START, VPXPI_EVT_ON_PREPARE_FRAME, VPXPI_NAMESPACE};
+
+    #[test]
+    fn test_reference_counting() {
+        let vpx_api = TestVPXPluginAPI::init();
+        let mut msg_api = TestMsgPluginAPI::init(&vpx_api);
+        let bus = MsgBus::new(TEST_SESSION_ID, &mut msg_api, Arc::default());
+
+        let start = bus
+            .msg_id(VPXPI_NAMESPACE, VPXPI_EVT_ON_GAME_START)
+            .unwrap();
+        let start_again = bus
+            .msg_id(VPXPI_NAMESPACE, VPXPI_EVT_ON_GAME_START)
+            .unwrap();
+        let start_clone = start.clone();
+        assert_eq!(start, start_again);
+        assert_eq!(bus.ids.borrow().len(), 1);
+
+        drop(start);
+        drop(start_again);
+        assert_eq!(bus.ids.borrow().len(), 1);
+        drop(start_clone);
+        assert_eq!(bus.ids.borrow().len(), 0);
+
+        // handles that outlive the unload do nothing when dropped
+        let frame = bus
+            .msg_id(VPXPI_NAMESPACE, VPXPI_EVT_ON_PREPARE_FRAME)
+            .unwrap();
+        bus.ids.borrow_mut().release_all();
+        assert_eq!(bus.ids.borrow().len(), 0);
+        drop(frame.clone());
+        drop(frame);
+        assert_eq!(bus.ids.borrow().len(), 0);
+    }
+
+    #[test]
+    fn test_multiple_subscribers() {
+        let vpx_api = TestVPXPluginAPI::init();
+        let mut msg_api = TestMsgPluginAPI::init(&vpx_api);
+        let bus = Rc::new(MsgBus::new(TEST_SESSION_ID, &mut msg_api, Arc::default()));
+        let calls = Rc::new(Cell::new(0));
+
+        let calls_a = Rc::clone(&calls);
+        let a = bus
+            .subscribe(
+                VPXPI_NAMESPACE,
+                VPXPI_EVT_ON_PREPARE_FRAME,
+                Rc::new(move |_, _| calls_a.set(calls_a.get() + 1)),
+            )
+            .unwrap();
+        let calls_b = Rc::clone(&calls);
+        let b = bus
+            .subscribe(
+                VPXPI_NAMESPACE,
+                VPXPI_EVT_ON_PREPARE_FRAME,
+                Rc::new(move |_, _| calls_b.set(calls_b.get() + 10)),
+            )
+            .unwrap();
+        let msg_id = a.msg_id().raw();
+        assert_eq!(bus.subscriber_count(msg_id), 2);
+        assert_eq!(TestMsgPluginAPI::subscriptions(msg_id), 1);
+
+        TestMsgPluginAPI::broadcast(msg_id);
+        assert_eq!(calls.get(), 11);
+
+        drop(a);
+        TestMsgPluginAPI::broadcast(msg_id);
+        assert_eq!(calls.get(), 21);
+
+        drop(b);
+        assert_eq!(bus.subscriber_count(msg_id), 0);
+        assert_eq!(TestMsgPluginAPI::subscriptions(msg_id), 0);
+        assert_eq!(bus.ids.borrow().len(), 0);
+    }
+}
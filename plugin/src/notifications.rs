@@ -0,0 +1,255 @@
+use crate::bindings;
+use crate::error::{require, VpxError};
+use crate::scheduler::{Scheduler, TaskHandle};
+use log::{info, warn};
+use std::cell::{Cell, RefCell};
+use std::ffi::{c_uint, CString};
+use std::rc::{Rc, Weak};
+use std::time::{Duration, Instant};
+
+/// Pushes and updates notifications, shared with the handles that are given out.
+#[derive(Default)]
+pub(crate) struct Notifier {
+    /// Null while the plugin is not loaded.
+    vpx: Cell<*mut bindings::VPXPluginAPI>,
+}
+
+impl Notifier {
+    pub(crate) fn set_vpx(&self, vpx: *mut bindings::VPXPluginAPI) {
+        self.vpx.set(vpx);
+    }
+
+    fn vpx(&self) -> Result<&bindings::VPXPluginAPI, VpxError> {
+        unsafe { self.vpx.get().as_ref() }.ok_or(VpxError::ApiUnavailable)
+    }
+
+    pub(crate) fn push(
+        self: &Rc<Self>,
+        message: &str,
+        length_ms: u32,
+    ) -> Result<NotificationHandle, VpxError> {
+        info!("push_notification({message}, {length_ms} ms)");
+        let push_notification = require(self.vpx()?.PushNotification, "PushNotification")?;
+        let message_c = CString::new(message)?;
+        let id = unsafe { push_notification(message_c.as_ptr(), length_ms) };
+        Ok(NotificationHandle {
+            notifier: Rc::downgrade(self),
+            id,
+        })
+    }
+
+    fn update(&self, id: c_uint, message: &str, length_ms: u32) -> Result<(), VpxError> {
+        let update_notification = require(self.vpx()?.UpdateNotification, "UpdateNotification")?;
+        let message_c = CString::new(message)?;
+        unsafe { update_notification(id, message_c.as_ptr(), length_ms) };
+        Ok(())
+    }
+
+    fn can_update(&self) -> bool {
+        self.vpx().is_ok_and(|vpx| vpx.UpdateNotification.is_some())
+    }
+}
+
+/// A notification that is shown to the user, dropping the handle leaves it on screen.
+#[derive(Debug)]
+pub struct NotificationHandle {
+    notifier: Weak<Notifier>,
+    id: c_uint,
+}
+
+impl NotificationHandle {
+    /// Id assigned by the host.
+    pub fn id(&self) -> c_uint {
+        self.id
+    }
+
+    /// Replaces the text, the notification is shown for `length_ms` from now on.
+    pub fn update(&self, message: &str, length_ms: u32) -> Result<(), VpxError> {
+        let notifier = self.notifier.upgrade().ok_or(VpxError::Unloaded)?;
+        notifier.update(self.id, message, length_ms)
+    }
+
+    /// Removes the notification right away.
+    pub fn dismiss(self) -> Result<(), VpxError> {
+        self.update("", 0)
+    }
+}
+
+struct Shown {
+    handle: NotificationHandle,
+    until: Instant,
+}
+
+#[derive(Default)]
+struct LiveState {
+    shown: Option<Shown>,
+    last_change: Option<Instant>,
+    /// Newest text that arrived too soon after the previous one.
+    pending: Option<String>,
+    flush: Option<TaskHandle>,
+}
+
+struct Live {
+    notifier: Weak<Notifier>,
+    scheduler: Weak<Scheduler>,
+    length: Duration,
+    min_interval: Duration,
+    state: RefCell<LiveState>,
+}
+
+/// A single notification for frequent events, new text replaces the text on screen instead of
+/// stacking up notifications.
+///
+/// Changes closer together than `min_interval` are coalesced, only the newest text is shown
+/// once the interval passed. Falls back to pushing a new notification per change on hosts
+/// without `UpdateNotification`. Get one with [`crate::VPXApi::live_notification`].
+pub struct LiveNotification {
+    live: Rc<Live>,
+}
+
+impl LiveNotification {
+    pub(crate) fn new(
+        notifier: &Rc<Notifier>,
+        scheduler: &Rc<Scheduler>,
+        length: Duration,
+        min_interval: Duration,
+    ) -> Self {
+        Self {
+            live: Rc::new(Live {
+                notifier: Rc::downgrade(notifier),
+                scheduler: Rc::downgrade(scheduler),
+                length,
+                min_interval,
+                state: RefCell::default(),
+            }),
+        }
+    }
+
+    pub fn show(&self, message: &str) -> Result<(), VpxError> {
+        let live = &self.live;
+        let mut state = live.state.borrow_mut();
+        let wait = state
+            .last_change
+            .map(|last| live.min_interval.saturating_sub(last.elapsed()))
+            .unwrap_or_default();
+        if wait.is_zero() {
+            drop(state);
+            return live.display(message);
+        }
+        state.pending = Some(message.to_string());
+        if state.flush.is_none() {
+            let scheduler = live.scheduler.upgrade().ok_or(VpxError::Unloaded)?;
+            let weak = Rc::downgrade(live);
+            let task = scheduler.run_once(
+                wait,
+                Box::new(move || {
+                    if let Some(live) = weak.upgrade() {
+                        live.flush();
+                    }
+                }),
+            )?;
+            state.flush = Some(task);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for LiveNotification {
+    fn drop(&mut self) {
+        if let Some(flush) = self.live.state.borrow_mut().flush.take() {
+            flush.cancel();
+        }
+    }
+}
+
+impl Live {
+    fn display(&self, message: &str) -> Result<(), VpxError> {
+        let notifier = self.notifier.upgrade().ok_or(VpxError::Unloaded)?;
+        let now = Instant::now();
+        let length_ms = self.length.as_millis().min(u32::MAX as u128) as u32;
+        let mut state = self.state.borrow_mut();
+        state.last_change = Some(now);
+        let visible = state.shown.as_ref().filter(|shown| shown.until > now);
+        match visible {
+            Some(shown) if notifier.can_update() => shown.handle.update(message, length_ms)?,
+            _ => {
+                let handle = notifier.push(message, length_ms)?;
+                state.shown = Some(Shown { handle, until: now });
+            }
+        }
+        if let Some(shown) = &mut state.shown {
+            shown.until = now + self.length;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) {
+        let pending = {
+            let mut state = self.state.borrow_mut();
+            state.flush = None;
+            state.pending.take()
+        };
+        if let Some(message) = pending {
+            if let Err(error) = self.display(&message) {
+                warn!("Failed to show notification: {error}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::{wrapped_api_with, TestMsgPluginAPI, TestVPXPluginAPI};
+    use crate::VPXApi;
+    use std::time::Duration;
+
+    #[test]
+    fn test_update_and_dismiss() {
+        let mut vpx_api = TestVPXPluginAPI::init();
+        TestVPXPluginAPI::enable_notifications(&mut vpx_api);
+        let api = wrapped_api_with(vpx_api);
+        let api: &dyn VPXApi = &*api;
+
+        let handle = api.push_notification("Loading", 5000).unwrap();
+        handle.update("Loaded", 1000).unwrap();
+        let other = api.push_notification("Other", 5000).unwrap();
+        other.dismiss().unwrap();
+        assert_eq!(
+            TestVPXPluginAPI::notifications(),
+            vec![("Loaded".to_string(), 1000), ("".to_string(), 0)]
+        );
+    }
+
+    #[test]
+    fn test_live_notification_coalesces() {
+        let mut vpx_api = TestVPXPluginAPI::init();
+        TestVPXPluginAPI::enable_notifications(&mut vpx_api);
+        let api = wrapped_api_with(vpx_api);
+        let api: &dyn VPXApi = &*api;
+
+        let live = api.live_notification(Duration::from_secs(60), Duration::from_secs(60));
+        live.show("FPS drop: 50").unwrap();
+        live.show("FPS drop: 40").unwrap();
+        live.show("FPS drop: 30").unwrap();
+        assert_eq!(
+            TestVPXPluginAPI::notifications(),
+            vec![("FPS drop: 50".to_string(), 60000)]
+        );
+
+        // the newest text is shown once the interval passed, in the same notification
+        assert_eq!(TestMsgPluginAPI::run_pending(), 1);
+        assert_eq!(
+            TestVPXPluginAPI::notifications(),
+            vec![("FPS drop: 30".to_string(), 60000)]
+        );
+
+        // a pending update is dropped with the notification
+        live.show("FPS drop: 20").unwrap();
+        drop(live);
+        TestMsgPluginAPI::run_pending();
+        assert_eq!(
+            TestVPXPluginAPI::notifications(),
+            vec![("FPS drop: 30".to_string(), 60000)]
+        );
+    }
+}
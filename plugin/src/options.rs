@@ -0,0 +1,386 @@
+//! Typed plugin options, usually derived with `#[derive(PluginOptions)]`.
+//!
+//! ```ignore
+//! #[derive(OptionChoice, Clone, Copy, Default)]
+//! enum Color {
+//!     #[default]
+//!     Red,
+//!     Blue,
+//! }
+//!
+//! #[derive(PluginOptions)]
+//! #[options(page = "rainbow")]
+//! struct RainbowOptions {
+//!     #[option(name = "Use red or blue")]
+//!     color: Color,
+//!     #[option(name = "Speed", min = 0.1, max = 5.0, step = 0.1, default = 1.0)]
+//!     speed: f32,
+//!     /// 0 to 100, the host stores it as a fraction
+//!     #[option(name = "Brightness", unit = "percent", min = 0.0, max = 100.0, step = 5.0)]
+//!     brightness: f32,
+//!     #[option(name = "Rotate", default = true, show = "tweak")]
+//!     rotate: bool,
+//! }
+//!
+//! let options = RainbowOptions::load(api)?;
+//! ```
+//!
+//! Use [`Options`] to keep the values up to date while the user tweaks them in game.
+
+use crate::bindings::{self, OptionUnit};
+use crate::messages::SettingsChanged;
+use crate::{ApiHandle, Subscription, VPXApi, VpxError};
+use log::{info, warn};
+use std::cell::{Ref, RefCell};
+use std::rc::Rc;
+
+/// Labels used for boolean options.
+pub const BOOL_LABELS: [&str; 2] = ["Off", "On"];
+
+/// Show an option both in the UI and in the tweak menu.
+pub const SHOW_ALL: u32 = bindings::VPX_OPT_SHOW_UI | bindings::VPX_OPT_SHOW_TWEAK;
+
+/// A struct of options that is read from the host in one go.
+pub trait PluginOptions: Sized {
+    /// The options page, which is the plugin id.
+    const PAGE_ID: &'static str;
+
+    /// Registers every option with the host and returns their current values.
+    fn load(api: &dyn VPXApi) -> Result<Self, VpxError>;
+}
+
+/// Everything the host needs to know about an option besides its type.
+pub struct OptionDef<'a, T> {
+    pub page_id: &'a str,
+    pub id: &'a str,
+    pub name: &'a str,
+    /// Combination of `VPX_OPT_SHOW_UI` and `VPX_OPT_SHOW_TWEAK`
+    pub show_mask: u32,
+    pub default: T,
+    /// Not used by types with a fixed range like `bool` and choices.
+    pub min: Option<T>,
+    pub max: Option<T>,
+    pub step: Option<T>,
+    pub unit: OptionUnit,
+}
+
+/// A type that can be stored in an option.
+pub trait OptionValue: Sized {
+    fn read(api: &dyn VPXApi, option: &OptionDef<'_, Self>) -> Result<Self, VpxError>;
+}
+
+/// An enum of which one variant can be selected, derive it with `#[derive(OptionChoice)]`.
+pub trait OptionChoice: Sized + Copy {
+    /// Label shown to the user for each variant, in variant order.
+    const LABELS: &'static [&'static str];
+
+    fn from_index(index: usize) -> Option<Self>;
+    fn index(self) -> usize;
+}
+
+/// Values are in the unit the user sees, see [`OptionUnit::to_host`].
+impl OptionValue for f32 {
+    fn read(api: &dyn VPXApi, option: &OptionDef<'_, Self>) -> Result<Self, VpxError> {
+        let unit = option.unit;
+        let value = get_raw(
+            api,
+            option,
+            unit.to_host(option.min.unwrap_or(0.0)),
+            unit.to_host(option.max.unwrap_or(1.0)),
+            unit.to_host(option.step.unwrap_or(0.1)),
+            unit.to_host(option.default),
+            &[],
+        )?;
+        Ok(unit.from_host(value))
+    }
+}
+
+impl OptionValue for i32 {
+    fn read(api: &dyn VPXApi, option: &OptionDef<'_, Self>) -> Result<Self, VpxError> {
+        let value = get_raw(
+            api,
+            option,
+            option.min.unwrap_or(0) as f32,
+            option.max.unwrap_or(1) as f32,
+            option.step.unwrap_or(1) as f32,
+            option.default as f32,
+            &[],
+        )?;
+        Ok(value.round() as i32)
+    }
+}
+
+impl OptionValue for bool {
+    fn read(api: &dyn VPXApi, option: &OptionDef<'_, Self>) -> Result<Self, VpxError> {
+        let default = if option.default { 1.0 } else { 0.0 };
+        let value = get_raw(api, option, 0.0, 1.0, 1.0, default, &BOOL_LABELS)?;
+        Ok(value >= 0.5)
+    }
+}
+
+/// Reads a choice option, used by the `OptionValue` impl that `#[derive(OptionChoice)]`
+/// generates.
+pub fn read_choice<E: OptionChoice>(
+    api: &dyn VPXApi,
+    option: &OptionDef<'_, E>,
+) -> Result<E, VpxError> {
+    let last = E::LABELS.len().saturating_sub(1) as f32;
+    let value = get_raw(
+        api,
+        option,
+        0.0,
+        last,
+        1.0,
+        option.default.index() as f32,
+        E::LABELS,
+    )?;
+    match E::from_index(value.round().max(0.0) as usize) {
+        Some(choice) => Ok(choice),
+        None => {
+            warn!(
+                "Option {} has unknown value {value}, using the default",
+                option.id
+            );
+            Ok(option.default)
+        }
+    }
+}
+
+type ChangeCallback<T> = Rc<dyn Fn(&T, &T)>;
+
+/// The values and callback are cloned out before calling the callback, so it can use the
+/// options freely, even when it causes another reload.
+struct Shared<T> {
+    current: RefCell<Rc<T>>,
+    on_changed: RefCell<Option<ChangeCallback<T>>>,
+}
+
+/// Options that are read again every time the host reports changed settings, create it
+/// with `api.options::<T>()`.
+///
+/// Stops updating when dropped or when the plugin unloads.
+pub struct Options<T> {
+    shared: Rc<Shared<T>>,
+    _subscription: Subscription,
+}
+
+impl<T: PluginOptions + PartialEq + 'static> Options<T> {
+    pub(crate) fn new(api: &dyn VPXApi) -> Result<Self, VpxError> {
+        let shared = Rc::new(Shared {
+            current: RefCell::new(Rc::new(T::load(api)?)),
+            on_changed: RefCell::new(None),
+        });
+        let handle = api.handle();
+        let shared_clone = Rc::clone(&shared);
+        let subscription =
+            api.subscribe::<SettingsChanged>(move |_| reload(&handle, &shared_clone))?;
+        Ok(Self {
+            shared,
+            _subscription: subscription,
+        })
+    }
+
+    /// The current values, do not hold on to the reference across host callbacks.
+    pub fn get(&self) -> Ref<'_, T> {
+        Ref::map(self.shared.current.borrow(), |current| &**current)
+    }
+
+    /// Called with the old and new values whenever a reload changed any of them, replaces a
+    /// previously set callback.
+    pub fn on_changed(&self, callback: impl Fn(&T, &T) + 'static) {
+        self.shared.on_changed.replace(Some(Rc::new(callback)));
+    }
+}
+
+fn reload<T: PluginOptions + PartialEq>(handle: &ApiHandle, shared: &Shared<T>) {
+    let options = match handle.with_api(T::load) {
+        Ok(Ok(options)) => options,
+        Ok(Err(error)) | Err(error) => {
+            warn!("Keeping the previous options of {}: {error}", T::PAGE_ID);
+            return;
+        }
+    };
+    if **shared.current.borrow() == options {
+        return;
+    }
+    info!("Options of {} changed", T::PAGE_ID);
+    let new = Rc::new(options);
+    let old = shared.current.replace(Rc::clone(&new));
+    let on_changed = shared.on_changed.borrow().clone();
+    if let Some(on_changed) = on_changed {
+        on_changed(&old, &new);
+    }
+}
+
+fn get_raw<T>(
+    api: &dyn VPXApi,
+    option: &OptionDef<'_, T>,
+    min: f32,
+    max: f32,
+    step: f32,
+    default: f32,
+    labels: &[&str],
+) -> Result<f32, VpxError> {
+    api.get_option(
+        option.page_id,
+        option.id,
+        option.show_mask,
+        option.name,
+        min,
+        max,
+        step,
+        default,
+        option.unit,
+        labels,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{wrapped_api, TestMsgPluginAPI, TestVPXPluginAPI};
+    use crate::{Message, OptionChoice, PluginOptions};
+
+    #[derive(OptionChoice, Clone, Copy, Debug, PartialEq, Default)]
+    enum Color {
+        Red,
+        #[default]
+        #[option(label = "Deep blue")]
+        Blue,
+    }
+
+    #[derive(PluginOptions, Debug, PartialEq)]
+    #[options(page = "test")]
+    struct TestOptions {
+        #[option(name = "Color")]
+        color: Color,
+        #[option(name = "Speed", min = 0.5, max = 2.0, step = 0.5, default = 1.5)]
+        speed: f32,
+        #[option(name = "Balls", min = 1, max = 6, default = 3, show = "ui")]
+        balls: i32,
+        #[option(id = "on", name = "Enabled", default = true)]
+        enabled: bool,
+        #[option(
+            name = "Volume",
+            unit = "percent",
+            min = 0.0,
+            max = 100.0,
+            default = 80.0
+        )]
+        volume: f32,
+        #[option(
+            name = "Reach (cm)",
+            unit = "distance",
+            min = 0.0,
+            max = 100.0 * crate::VPU_PER_CM,
+            default = 10.0 * crate::VPU_PER_CM
+        )]
+        reach: f32,
+    }
+
+    #[test]
+    fn test_derived_options() {
+        assert_eq!(Color::LABELS, &["Red", "Deep blue"]);
+        assert_eq!(Color::from_index(1), Some(Color::Blue));
+        assert_eq!(Color::from_index(2), None);
+
+        let api = wrapped_api();
+
+        // the test host hands back the default of options that were never set
+        let options = TestOptions::load(&*api).unwrap();
+        assert_eq!(options.color, Color::Blue);
+        assert_eq!(options.speed, 1.5);
+        assert_eq!(options.balls, 3);
+        assert!(options.enabled);
+        assert_eq!(options.volume, 80.0);
+        assert!((options.reach - 10.0 * crate::VPU_PER_CM).abs() < 1e-3);
+
+        TestVPXPluginAPI::set_option("test", "color", 0.0);
+        TestVPXPluginAPI::set_option("test", "balls", 5.0);
+        TestVPXPluginAPI::set_option("test", "on", 0.0);
+        // the host stores percentages as fractions
+        TestVPXPluginAPI::set_option("test", "volume", 0.25);
+        // and distances as cm
+        TestVPXPluginAPI::set_option("test", "reach", 4.0);
+        let options = TestOptions::load(&*api).unwrap();
+        assert_eq!(options.color, Color::Red);
+        assert_eq!(options.balls, 5);
+        assert!(!options.enabled);
+        assert_eq!(options.volume, 25.0);
+        assert_eq!(options.reach, 4.0 * crate::VPU_PER_CM);
+    }
+
+    #[test]
+    fn test_reload_on_settings_changed() {
+        let api = wrapped_api();
+        let api: &dyn VPXApi = &*api;
+
+        let options = api.options::<TestOptions>().unwrap();
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let changes_clone = Rc::clone(&changes);
+        options.on_changed(move |old: &TestOptions, new: &TestOptions| {
+            changes_clone.borrow_mut().push((old.balls, new.balls));
+        });
+        let settings_changed = api
+            .get_msg_id(SettingsChanged::NAMESPACE, SettingsChanged::NAME)
+            .unwrap();
+
+        // nothing changed
+        TestMsgPluginAPI::broadcast(settings_changed.raw());
+        assert!(changes.borrow().is_empty());
+
+        TestVPXPluginAPI::set_option("test", "balls", 4.0);
+        TestMsgPluginAPI::broadcast(settings_changed.raw());
+        assert_eq!(options.get().balls, 4);
+        assert_eq!(*changes.borrow(), vec![(3, 4)]);
+
+        drop(options);
+        TestVPXPluginAPI::set_option("test", "balls", 5.0);
+        TestMsgPluginAPI::broadcast(settings_changed.raw());
+        assert_eq!(*changes.borrow(), vec![(3, 4)]);
+    }
+
+    #[test]
+    fn test_reload_from_callback() {
+        let api = wrapped_api();
+        let api: &dyn VPXApi = &*api;
+
+        let options = api.options::<TestOptions>().unwrap();
+        let settings_changed = api
+            .get_msg_id(SettingsChanged::NAMESPACE, SettingsChanged::NAME)
+            .unwrap()
+            .raw();
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let changes_clone = Rc::clone(&changes);
+        options.on_changed(move |old: &TestOptions, new: &TestOptions| {
+            changes_clone.borrow_mut().push((old.balls, new.balls));
+            // e.g. a callback that adjusts another option
+            if new.balls == 4 {
+                TestVPXPluginAPI::set_option("test", "balls", 5.0);
+                TestMsgPluginAPI::broadcast(settings_changed);
+            }
+        });
+
+        TestVPXPluginAPI::set_option("test", "balls", 4.0);
+        TestMsgPluginAPI::broadcast(settings_changed);
+        assert_eq!(*changes.borrow(), vec![(3, 4), (4, 5)]);
+        assert_eq!(options.get().balls, 5);
+    }
+
+    #[test]
+    fn test_choice_option() {
+        let mut api = wrapped_api();
+
+        TestVPXPluginAPI::set_option("test", "choice", 0.0);
+        for _ in 0..2 {
+            let dyn_api: &dyn VPXApi = &*api;
+            let choice =
+                dyn_api.get_choice_option("test", "choice", SHOW_ALL, "Choice", Color::Blue);
+            assert_eq!(choice, Ok(Color::Red));
+        }
+        // the labels are handed out once and kept until unload
+        assert_eq!(api.labels.len(), 1);
+        api.release_host_resources();
+        assert_eq!(api.labels.len(), 0);
+    }
+}
@@ -0,0 +1,149 @@
+//! Keeps panics from unwinding into the host, which would abort VPinball.
+
+use crate::bindings;
+use log::error;
+use std::any::Any;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use std::sync::Once;
+
+thread_local! {
+    /// Location of the last panic on this thread, recorded by our panic hook.
+    static LAST_PANIC_LOCATION: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+static INSTALL_HOOK: Once = Once::new();
+
+/// Chains a panic hook that records where the panic happened, the payload alone does not
+/// contain the location.
+fn install_hook() {
+    INSTALL_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if let Some(location) = info.location() {
+                let location = location.to_string();
+                LAST_PANIC_LOCATION.with_borrow_mut(|l| *l = Some(location));
+            }
+            previous(info);
+        }));
+    });
+}
+
+fn payload_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic payload"
+    }
+}
+
+/// Runs `f`, logging and swallowing any panic instead of unwinding into the host.
+///
+/// Use this for code that is called directly by the host, `context` ends up in the log.
+pub fn catch_panic<R>(context: &str, f: impl FnOnce() -> R) -> Option<R> {
+    catch(context, f).ok()
+}
+
+fn catch<R>(context: &str, f: impl FnOnce() -> R) -> Result<R, String> {
+    install_hook();
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| {
+        let message = payload_message(payload.as_ref());
+        let location = LAST_PANIC_LOCATION
+            .take()
+            .unwrap_or_else(|| "unknown location".to_string());
+        error!("Panic in {context} at {location}: {message}");
+        message.to_string()
+    })
+}
+
+/// Tracks whether the plugin panicked, after which all host callbacks are ignored.
+#[derive(Default)]
+pub(crate) struct PanicState {
+    disabled: AtomicBool,
+    /// Used to notify the user about the panic, null if notifications are not wanted.
+    notify_vpx: AtomicPtr<bindings::VPXPluginAPI>,
+}
+
+impl PanicState {
+    pub(crate) fn is_disabled(&self) -> bool {
+        self.disabled.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set_notifier(&self, vpx: *mut bindings::VPXPluginAPI) {
+        self.notify_vpx.store(vpx, Ordering::Release);
+    }
+
+    /// Runs a host callback, a panic disables the plugin until it is loaded again.
+    pub(crate) fn guard<R>(&self, context: &str, f: impl FnOnce() -> R) -> Option<R> {
+        if self.is_disabled() {
+            return None;
+        }
+        match catch(context, f) {
+            Ok(result) => Some(result),
+            Err(message) => {
+                self.disabled.store(true, Ordering::Release);
+                error!("Plugin disabled after panic");
+                self.notify(&message);
+                None
+            }
+        }
+    }
+
+    fn notify(&self, message: &str) {
+        let vpx = self.notify_vpx.load(Ordering::Acquire);
+        if vpx.is_null() {
+            return;
+        }
+        let text = format!("Plugin disabled after an error: {message}").replace('\0', "");
+        let text = CString::new(text).unwrap();
+        unsafe {
+            if let Some(push_notification) = (*vpx).PushNotification {
+                push_notification(text.as_ptr(), 5000);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{wrapped_api, TestMsgPluginAPI};
+    use crate::VPXApi;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_panic_disables_callbacks() {
+        let api = wrapped_api();
+        let api: &dyn VPXApi = &*api;
+
+        let calls = Rc::new(Cell::new(0));
+        let calls_clone = Rc::clone(&calls);
+        let ok = api
+            .subscribe_msg(
+                "VPX",
+                "OnGameStart",
+                Box::new(move |_| calls_clone.set(calls_clone.get() + 1)),
+            )
+            .unwrap();
+        let failing = api
+            .subscribe_msg("VPX", "OnGameEnd", Box::new(|_| panic!("boom")))
+            .unwrap();
+
+        TestMsgPluginAPI::broadcast(ok.msg_id().raw());
+        TestMsgPluginAPI::broadcast(failing.msg_id().raw());
+        TestMsgPluginAPI::broadcast(ok.msg_id().raw());
+
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[test]
+    fn test_catch_panic() {
+        assert_eq!(catch_panic("test", || 1), Some(1));
+        assert_eq!(catch_panic("test", || -> i32 { panic!("boom") }), None);
+    }
+}
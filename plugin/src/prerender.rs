@@ -0,0 +1,111 @@
+use crate::bindings;
+use crate::error::{require, VpxError};
+use log::{info, warn};
+use std::cell::Cell;
+use std::rc::{Rc, Weak};
+
+/// Counts the guards that are alive, the host only hears about the first and the last one.
+#[derive(Default)]
+pub(crate) struct Prerender {
+    /// Null while the plugin is not loaded.
+    vpx: Cell<*mut bindings::VPXPluginAPI>,
+    guards: Cell<usize>,
+}
+
+impl Prerender {
+    pub(crate) fn set_vpx(&self, vpx: *mut bindings::VPXPluginAPI) {
+        self.vpx.set(vpx);
+    }
+
+    fn call(&self, disable: bool) -> Result<(), VpxError> {
+        let vpx = unsafe { self.vpx.get().as_ref() }.ok_or(VpxError::ApiUnavailable)?;
+        let disable_static_prerendering =
+            require(vpx.DisableStaticPrerendering, "DisableStaticPrerendering")?;
+        info!("DisableStaticPrerendering({disable})");
+        unsafe { disable_static_prerendering(disable as bindings::BOOL) };
+        Ok(())
+    }
+
+    pub(crate) fn acquire(self: &Rc<Self>) -> Result<StaticPrerenderGuard, VpxError> {
+        if self.guards.get() == 0 {
+            self.call(true)?;
+        }
+        self.guards.set(self.guards.get() + 1);
+        Ok(StaticPrerenderGuard {
+            prerender: Rc::downgrade(self),
+        })
+    }
+
+    fn release(&self) {
+        match self.guards.get() {
+            // already given back on unload
+            0 => {}
+            1 => {
+                self.guards.set(0);
+                if let Err(error) = self.call(false) {
+                    warn!("Failed to enable static prerendering: {error}");
+                }
+            }
+            guards => self.guards.set(guards - 1),
+        }
+    }
+
+    /// Enables static prerendering again on unload, guards dropped later do nothing.
+    pub(crate) fn release_all(&self) {
+        if self.guards.replace(0) > 0 {
+            if let Err(error) = self.call(false) {
+                warn!("Failed to enable static prerendering: {error}");
+            }
+        }
+    }
+}
+
+/// Keeps static prerendering disabled while alive, needed when a plugin moves the view.
+///
+/// Several guards can be alive at once, prerendering is enabled again when the last one is
+/// dropped or the plugin unloads. Get one with [`crate::VPXApi::disable_static_prerendering`].
+#[must_use = "static prerendering is enabled again when the guard is dropped"]
+pub struct StaticPrerenderGuard {
+    prerender: Weak<Prerender>,
+}
+
+impl Drop for StaticPrerenderGuard {
+    fn drop(&mut self) {
+        if let Some(prerender) = self.prerender.upgrade() {
+            prerender.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test::{wrapped_api_with, TestVPXPluginAPI};
+    use crate::VPXApi;
+
+    #[test]
+    fn test_guards_are_counted() {
+        let mut vpx_api = TestVPXPluginAPI::init();
+        TestVPXPluginAPI::enable_static_prerendering(&mut vpx_api);
+        let mut api = wrapped_api_with(vpx_api);
+
+        let camera = api.disable_static_prerendering().unwrap();
+        let tweak = api.disable_static_prerendering().unwrap();
+        assert_eq!(TestVPXPluginAPI::static_prerendering_calls(), vec![true]);
+        drop(camera);
+        assert_eq!(TestVPXPluginAPI::static_prerendering_calls(), vec![true]);
+        drop(tweak);
+        assert_eq!(
+            TestVPXPluginAPI::static_prerendering_calls(),
+            vec![true, false]
+        );
+
+        // unloading enables it again, the guard dropped afterwards does nothing
+        let guard = api.disable_static_prerendering().unwrap();
+        api.release_host_resources();
+        drop(guard);
+        assert_eq!(
+            TestVPXPluginAPI::static_prerendering_calls(),
+            vec![true, false, true, false]
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use crate::bindings;
+use crate::error::{require, VpxError};
+use crate::panic::PanicState;
+use log::{debug, warn};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+use std::time::Duration;
+
+enum Task {
+    Once(Box<dyn FnOnce()>),
+    Repeating {
+        interval: Duration,
+        callback: Rc<RefCell<dyn FnMut()>>,
+    },
+}
+
+/// Keeps the closures scheduled through `RunOnMainThread` until the host calls them.
+///
+/// The host has no way to cancel a scheduled callback, so cancelling only drops the closure
+/// and the host callback becomes a no-op once it fires.
+pub(crate) struct Scheduler {
+    msg: *mut bindings::MsgPluginAPI,
+    tasks: RefCell<HashMap<usize, Task>>,
+    next_id: Cell<usize>,
+    panic: Arc<PanicState>,
+}
+
+/// Passed as user data to the host, freed when the host calls back.
+struct Ticket {
+    scheduler: Weak<Scheduler>,
+    id: usize,
+}
+
+impl Scheduler {
+    pub(crate) fn new(msg: *mut bindings::MsgPluginAPI, panic: Arc<PanicState>) -> Self {
+        Self {
+            msg,
+            tasks: RefCell::new(HashMap::new()),
+            next_id: Cell::new(0),
+            panic,
+        }
+    }
+
+    pub(crate) fn run_once(
+        self: &Rc<Self>,
+        delay: Duration,
+        task: Box<dyn FnOnce()>,
+    ) -> Result<TaskHandle, VpxError> {
+        let id = self.insert(Task::Once(task));
+        self.post(id, delay)?;
+        Ok(TaskHandle {
+            scheduler: Rc::downgrade(self),
+            id,
+        })
+    }
+
+    pub(crate) fn repeat(
+        self: &Rc<Self>,
+        interval: Duration,
+        callback: Box<dyn FnMut()>,
+    ) -> Result<Timer, VpxError> {
+        let callback: Rc<RefCell<dyn FnMut()>> = Rc::new(RefCell::new(callback));
+        let id = self.insert(Task::Repeating { interval, callback });
+        self.post(id, interval)?;
+        Ok(Timer {
+            scheduler: Rc::downgrade(self),
+            id,
+        })
+    }
+
+    /// Drops all pending closures, used when the plugin unloads.
+    pub(crate) fn clear(&self) {
+        let tasks = self.tasks.take();
+        if !tasks.is_empty() {
+            debug!("Dropping {} pending main thread task(s)", tasks.len());
+        }
+    }
+
+    pub(crate) fn pending(&self) -> usize {
+        self.tasks.borrow().len()
+    }
+
+    fn insert(&self, task: Task) -> usize {
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.tasks.borrow_mut().insert(id, task);
+        id
+    }
+
+    fn cancel(&self, id: usize) {
+        // dropped outside the borrow, the closure may own other guards
+        let task = self.tasks.borrow_mut().remove(&id);
+        drop(task);
+    }
+
+    /// Hands the task to the host, the task is dropped again if the host can not run it.
+    fn post(self: &Rc<Self>, id: usize, delay: Duration) -> Result<(), VpxError> {
+        let run_on_main_thread =
+            match require(unsafe { (*self.msg).RunOnMainThread }, "RunOnMainThread") {
+                Ok(run_on_main_thread) => run_on_main_thread,
+                Err(error) => {
+                    self.cancel(id);
+                    return Err(error);
+                }
+            };
+        let ticket = Box::new(Ticket {
+            scheduler: Rc::downgrade(self),
+            id,
+        });
+        unsafe {
+            run_on_main_thread(
+                delay.as_secs_f64(),
+                Some(timer_trampoline),
+                Box::into_raw(ticket) as *mut c_void,
+            );
+        }
+        Ok(())
+    }
+
+    fn fire(self: &Rc<Self>, id: usize) {
+        let task = self.tasks.borrow_mut().remove(&id);
+        match task {
+            // cancelled in the meantime
+            None => {}
+            Some(Task::Once(task)) => task(),
+            Some(Task::Repeating { interval, callback }) => {
+                // put it back first so the callback is able to cancel its own timer
+                self.tasks.borrow_mut().insert(
+                    id,
+                    Task::Repeating {
+                        interval,
+                        callback: Rc::clone(&callback),
+                    },
+                );
+                (callback.borrow_mut())();
+                if self.tasks.borrow().contains_key(&id) {
+                    if let Err(error) = self.post(id, interval) {
+                        warn!("Timer stopped: {error}");
+                    }
+                }
+            }
+        }
+    }
+}
+
+unsafe extern "C" fn timer_trampoline(user_data: *mut c_void) {
+    let ticket = Box::from_raw(user_data as *mut Ticket);
+    if let Some(scheduler) = ticket.scheduler.upgrade() {
+        scheduler
+            .panic
+            .guard("main thread task", || scheduler.fire(ticket.id));
+    }
+}
+
+/// Handle to a closure scheduled with `run_on_main_thread`, dropping it does not cancel the task.
+pub struct TaskHandle {
+    scheduler: Weak<Scheduler>,
+    id: usize,
+}
+
+impl TaskHandle {
+    /// Prevents the task from running if it did not run yet.
+    pub fn cancel(self) {
+        if let Some(scheduler) = self.scheduler.upgrade() {
+            scheduler.cancel(self.id);
+        }
+    }
+}
+
+/// Repeatedly calls a closure on the main thread, dropping the timer stops it.
+#[must_use = "dropping a Timer immediately stops it"]
+pub struct Timer {
+    scheduler: Weak<Scheduler>,
+    id: usize,
+}
+
+impl Timer {
+    pub fn cancel(self) {
+        drop(self);
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if let Some(scheduler) = self.scheduler.upgrade() {
+            scheduler.cancel(self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{TestMsgPluginAPI, TestVPXPluginAPI};
+
+    #[test]
+    fn test_run_once_and_cancel() {
+        let vpx_api = TestVPXPluginAPI::init();
+        let mut msg_api = TestMsgPluginAPI::init(&vpx_api);
+        let scheduler = Rc::new(Scheduler::new(&mut msg_api, Arc::default()));
+        let runs = Rc::new(Cell::new(0));
+
+        let runs_clone = Rc::clone(&runs);
+        let _task = scheduler
+            .run_once(
+                Duration::from_millis(10),
+                Box::new(move || runs_clone.set(runs_clone.get() + 1)),
+            )
+            .unwrap();
+        let runs_clone = Rc::clone(&runs);
+        let cancelled = scheduler
+            .run_once(
+                Duration::ZERO,
+                Box::new(move || runs_clone.set(runs_clone.get() + 100)),
+            )
+            .unwrap();
+        cancelled.cancel();
+
+        assert_eq!(TestMsgPluginAPI::run_pending(), 2);
+        assert_eq!(runs.get(), 1);
+        assert_eq!(scheduler.pending(), 0);
+    }
+
+    #[test]
+    fn test_timer() {
+        let vpx_api = TestVPXPluginAPI::init();
+        let mut msg_api = TestMsgPluginAPI::init(&vpx_api);
+        let scheduler = Rc::new(Scheduler::new(&mut msg_api, Arc::default()));
+        let ticks = Rc::new(Cell::new(0));
+
+        let ticks_clone = Rc::clone(&ticks);
+        let timer = scheduler
+            .repeat(
+                Duration::from_secs(1),
+                Box::new(move || ticks_clone.set(ticks_clone.get() + 1)),
+            )
+            .unwrap();
+        TestMsgPluginAPI::run_pending();
+        TestMsgPluginAPI::run_pending();
+        assert_eq!(ticks.get(), 2);
+
+        drop(timer);
+        TestMsgPluginAPI::run_pending();
+        assert_eq!(ticks.get(), 2);
+        // the host no longer has anything scheduled for us
+        assert_eq!(TestMsgPluginAPI::run_pending(), 0);
+    }
+}
@@ -0,0 +1,1563 @@
+use crate::bindings::{
+    __IncompleteArrayField, ScriptArray, ScriptArrayDef, ScriptClassDef, ScriptClassMemberDef,
+    ScriptString, ScriptTypeNameDef, ScriptVariant, ScriptablePluginAPI, PSC_CALL_MAX_ARG_COUNT,
+    PSC_ERR_FAIL,
+};
+use crate::panic::PanicState;
+use crate::VpxError;
+use log::{error, info, warn};
+use std::alloc::Layout;
+use std::any::Any;
+use std::cell::{Cell, Ref, RefCell, RefMut};
+use std::ffi::{c_char, c_int, c_uint, c_void, CStr, CString};
+use std::fmt::{Debug, Display, Formatter};
+use std::rc::{Rc, Weak};
+use std::sync::Arc;
+
+/// Types a script member can take or return, named like in the host type library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptType {
+    Void,
+    Bool,
+    Int,
+    UInt,
+    Float,
+    Double,
+    String,
+    /// An object of a registered class, see [`ScriptRef`].
+    Object(&'static ObjectType),
+    /// A one dimensional array starting at index 0, passed as a `Vec`.
+    Array(ArrayType),
+}
+
+/// Element type of an array, every one is registered with the host as an array type named
+/// like `IntArray`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrayType {
+    Bool,
+    Int,
+    UInt,
+    Float,
+    Double,
+}
+
+impl ArrayType {
+    pub const ALL: [ArrayType; 5] = [
+        ArrayType::Bool,
+        ArrayType::Int,
+        ArrayType::UInt,
+        ArrayType::Float,
+        ArrayType::Double,
+    ];
+
+    fn name(self) -> &'static CStr {
+        match self {
+            ArrayType::Bool => c"BoolArray",
+            ArrayType::Int => c"IntArray",
+            ArrayType::UInt => c"UIntArray",
+            ArrayType::Float => c"FloatArray",
+            ArrayType::Double => c"DoubleArray",
+        }
+    }
+
+    pub fn element(self) -> ScriptType {
+        match self {
+            ArrayType::Bool => ScriptType::Bool,
+            ArrayType::Int => ScriptType::Int,
+            ArrayType::UInt => ScriptType::UInt,
+            ArrayType::Float => ScriptType::Float,
+            ArrayType::Double => ScriptType::Double,
+        }
+    }
+
+    /// Describes the array type for `RegisterScriptArrayType`.
+    fn definition(self) -> ScriptArrayDef {
+        ScriptArrayDef {
+            name: ScriptTypeNameDef {
+                name: self.name().as_ptr(),
+                id: 0,
+            },
+            type_: self.element().type_name(),
+            nDimensions: 1,
+            lowerBounds: [0],
+        }
+    }
+}
+
+/// A registered class objects are passed as, the host checks the class by its name.
+pub struct ObjectType {
+    name: &'static CStr,
+    /// Hands the host a reference, like `AddRef` would.
+    into_host: fn(&Rc<dyn Any>) -> *mut c_void,
+    /// Takes a reference for the plugin to an object the host passed.
+    from_host: unsafe fn(*mut c_void) -> Rc<dyn Any>,
+}
+
+impl PartialEq for ObjectType {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for ObjectType {}
+
+impl Debug for ObjectType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name.to_string_lossy())
+    }
+}
+
+impl ScriptType {
+    fn name(self) -> &'static CStr {
+        match self {
+            ScriptType::Void => c"void",
+            ScriptType::Bool => c"bool",
+            ScriptType::Int => c"int",
+            ScriptType::UInt => c"uint",
+            ScriptType::Float => c"float",
+            ScriptType::Double => c"double",
+            ScriptType::String => c"string",
+            ScriptType::Object(object_type) => object_type.name,
+            ScriptType::Array(array_type) => array_type.name(),
+        }
+    }
+
+    pub(crate) fn from_name(name: &CStr) -> Option<ScriptType> {
+        [
+            ScriptType::Void,
+            ScriptType::Bool,
+            ScriptType::Int,
+            ScriptType::UInt,
+            ScriptType::Float,
+            ScriptType::Double,
+            ScriptType::String,
+        ]
+        .into_iter()
+        .chain(ArrayType::ALL.map(ScriptType::Array))
+        .find(|ty| ty.name() == name)
+    }
+
+    fn type_name(self) -> ScriptTypeNameDef {
+        ScriptTypeNameDef {
+            name: self.name().as_ptr(),
+            id: 0,
+        }
+    }
+}
+
+/// A value passed between a script and a plugin. `Nothing` in a script is `Void`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptValue {
+    Void,
+    Bool(bool),
+    Int(i32),
+    UInt(u32),
+    Float(f32),
+    Double(f64),
+    String(String),
+    Object(ScriptObject),
+    Array(ArrayValue),
+}
+
+/// The elements of an array passed between a script and a plugin.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArrayValue {
+    Bool(Vec<bool>),
+    Int(Vec<i32>),
+    UInt(Vec<u32>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+}
+
+impl ArrayValue {
+    pub fn array_type(&self) -> ArrayType {
+        match self {
+            ArrayValue::Bool(_) => ArrayType::Bool,
+            ArrayValue::Int(_) => ArrayType::Int,
+            ArrayValue::UInt(_) => ArrayType::UInt,
+            ArrayValue::Float(_) => ArrayType::Float,
+            ArrayValue::Double(_) => ArrayType::Double,
+        }
+    }
+
+    fn empty(ty: ArrayType) -> ArrayValue {
+        match ty {
+            ArrayType::Bool => ArrayValue::Bool(Vec::new()),
+            ArrayType::Int => ArrayValue::Int(Vec::new()),
+            ArrayType::UInt => ArrayValue::UInt(Vec::new()),
+            ArrayType::Float => ArrayValue::Float(Vec::new()),
+            ArrayType::Double => ArrayValue::Double(Vec::new()),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `array` must be null or a one dimensional array of `ty` elements.
+    unsafe fn read(ty: ArrayType, array: *const ScriptArray) -> ArrayValue {
+        if array.is_null() {
+            return ArrayValue::empty(ty);
+        }
+        match ty {
+            ArrayType::Bool => ArrayValue::Bool(read_array(array)),
+            ArrayType::Int => ArrayValue::Int(read_array(array)),
+            ArrayType::UInt => ArrayValue::UInt(read_array(array)),
+            ArrayType::Float => ArrayValue::Float(read_array(array)),
+            ArrayType::Double => ArrayValue::Double(read_array(array)),
+        }
+    }
+
+    fn into_host(self) -> *mut ScriptArray {
+        match self {
+            ArrayValue::Bool(values) => alloc_array(&values),
+            ArrayValue::Int(values) => alloc_array(&values),
+            ArrayValue::UInt(values) => alloc_array(&values),
+            ArrayValue::Float(values) => alloc_array(&values),
+            ArrayValue::Double(values) => alloc_array(&values),
+        }
+    }
+}
+
+/// Where the elements start in a one dimensional `ScriptArray`, they follow its length.
+fn array_data_offset<E>() -> usize {
+    let lengths_end = std::mem::offset_of!(ScriptArray, lengths) + size_of::<c_uint>();
+    lengths_end.next_multiple_of(align_of::<E>())
+}
+
+fn array_layout<E>(len: usize) -> Layout {
+    let size = array_data_offset::<E>() + len * size_of::<E>();
+    Layout::from_size_align(
+        size.max(size_of::<ScriptArray>()),
+        align_of::<ScriptArray>().max(align_of::<E>()),
+    )
+    .unwrap()
+}
+
+unsafe fn array_data<E>(array: *const ScriptArray) -> *const E {
+    array.cast::<u8>().add(array_data_offset::<E>()).cast()
+}
+
+unsafe fn array_len(array: *const ScriptArray) -> usize {
+    *std::ptr::addr_of!((*array).lengths).cast::<c_uint>() as usize
+}
+
+unsafe fn read_array<E: Copy>(array: *const ScriptArray) -> Vec<E> {
+    std::slice::from_raw_parts(array_data::<E>(array), array_len(array)).to_vec()
+}
+
+/// Copies the elements into an array the host frees through `Release`.
+fn alloc_array<E: Copy>(values: &[E]) -> *mut ScriptArray {
+    let layout = array_layout::<E>(values.len());
+    unsafe {
+        let array = std::alloc::alloc_zeroed(layout) as *mut ScriptArray;
+        if array.is_null() {
+            std::alloc::handle_alloc_error(layout);
+        }
+        std::ptr::addr_of_mut!((*array).Release).write(Some(release_array::<E>));
+        *std::ptr::addr_of_mut!((*array).lengths).cast::<c_uint>() = values.len() as c_uint;
+        let data = array_data::<E>(array) as *mut E;
+        std::ptr::copy_nonoverlapping(values.as_ptr(), data, values.len());
+        array
+    }
+}
+
+unsafe extern "C" fn release_array<E>(me: *mut ScriptArray) {
+    std::alloc::dealloc(me.cast(), array_layout::<E>(array_len(me)));
+}
+
+/// An object of a registered class whatever its Rust type, [`ScriptRef`] is the typed version.
+#[derive(Clone)]
+pub struct ScriptObject {
+    object_type: &'static ObjectType,
+    instance: Rc<dyn Any>,
+}
+
+impl PartialEq for ScriptObject {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(Rc::as_ptr(&self.instance), Rc::as_ptr(&other.instance))
+    }
+}
+
+impl Debug for ScriptObject {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ScriptObject")
+            .field(self.object_type)
+            .finish()
+    }
+}
+
+impl ScriptValue {
+    pub fn script_type(&self) -> ScriptType {
+        match self {
+            ScriptValue::Void => ScriptType::Void,
+            ScriptValue::Bool(_) => ScriptType::Bool,
+            ScriptValue::Int(_) => ScriptType::Int,
+            ScriptValue::UInt(_) => ScriptType::UInt,
+            ScriptValue::Float(_) => ScriptType::Float,
+            ScriptValue::Double(_) => ScriptType::Double,
+            ScriptValue::String(_) => ScriptType::String,
+            ScriptValue::Object(object) => ScriptType::Object(object.object_type),
+            ScriptValue::Array(array) => ScriptType::Array(array.array_type()),
+        }
+    }
+
+    /// Converts between the numeric types, `None` if the value can not be represented. `Void`
+    /// stays `Void` for objects, scripts see it as `Nothing`.
+    pub fn convert(self, to: ScriptType) -> Option<ScriptValue> {
+        if self.script_type() == to {
+            return Some(self);
+        }
+        if let (ScriptValue::Void, ScriptType::Object(_)) = (&self, to) {
+            return Some(self);
+        }
+        let number = match self {
+            ScriptValue::Bool(value) => f64::from(u8::from(value)),
+            ScriptValue::Int(value) => f64::from(value),
+            ScriptValue::UInt(value) => f64::from(value),
+            ScriptValue::Float(value) => f64::from(value),
+            ScriptValue::Double(value) => value,
+            ScriptValue::Void
+            | ScriptValue::String(_)
+            | ScriptValue::Object(_)
+            | ScriptValue::Array(_) => return None,
+        };
+        match to {
+            ScriptType::Bool => Some(ScriptValue::Bool(number != 0.0)),
+            ScriptType::Int
+                if number.fract() == 0.0
+                    && (f64::from(i32::MIN)..=f64::from(i32::MAX)).contains(&number) =>
+            {
+                Some(ScriptValue::Int(number as i32))
+            }
+            ScriptType::UInt
+                if number.fract() == 0.0 && (0.0..=f64::from(u32::MAX)).contains(&number) =>
+            {
+                Some(ScriptValue::UInt(number as u32))
+            }
+            ScriptType::Float => Some(ScriptValue::Float(number as f32)),
+            ScriptType::Double => Some(ScriptValue::Double(number)),
+            _ => None,
+        }
+    }
+
+    /// # Safety
+    ///
+    /// `variant` must hold a value of type `ty`.
+    pub(crate) unsafe fn read(ty: ScriptType, variant: &ScriptVariant) -> ScriptValue {
+        match ty {
+            ScriptType::Void => ScriptValue::Void,
+            ScriptType::Bool => ScriptValue::Bool(variant.vBool),
+            ScriptType::Int => ScriptValue::Int(variant.vInt),
+            ScriptType::UInt => ScriptValue::UInt(variant.vUInt),
+            ScriptType::Float => ScriptValue::Float(variant.vFloat),
+            ScriptType::Double => ScriptValue::Double(variant.vDouble),
+            ScriptType::String => {
+                let string = variant.vString.string;
+                if string.is_null() {
+                    ScriptValue::String(String::new())
+                } else {
+                    ScriptValue::String(CStr::from_ptr(string).to_string_lossy().into_owned())
+                }
+            }
+            ScriptType::Object(object_type) => {
+                if variant.vObject.is_null() {
+                    ScriptValue::Void
+                } else {
+                    ScriptValue::Object(ScriptObject {
+                        object_type,
+                        instance: (object_type.from_host)(variant.vObject),
+                    })
+                }
+            }
+            ScriptType::Array(array_type) => {
+                ScriptValue::Array(ArrayValue::read(array_type, variant.vArray))
+            }
+        }
+    }
+
+    /// Strings and arrays are handed over to the host, which frees them through `Release`,
+    /// objects get a reference the host drops with their `Release` member.
+    pub(crate) fn write(self, variant: &mut ScriptVariant) {
+        match self {
+            ScriptValue::Void => {}
+            ScriptValue::Bool(value) => variant.vBool = value,
+            ScriptValue::Int(value) => variant.vInt = value,
+            ScriptValue::UInt(value) => variant.vUInt = value,
+            ScriptValue::Float(value) => variant.vFloat = value,
+            ScriptValue::Double(value) => variant.vDouble = value,
+            ScriptValue::String(value) => {
+                let string = CString::new(value.replace('\0', "")).unwrap_or_default();
+                variant.vString = ScriptString {
+                    Release: Some(release_string),
+                    string: string.into_raw(),
+                };
+            }
+            ScriptValue::Object(object) => {
+                variant.vObject = (object.object_type.into_host)(&object.instance);
+            }
+            ScriptValue::Array(array) => variant.vArray = array.into_host(),
+        }
+    }
+
+    fn default_of(ty: ScriptType) -> ScriptValue {
+        match ty {
+            ScriptType::Void | ScriptType::Object(_) => ScriptValue::Void,
+            ScriptType::Bool => ScriptValue::Bool(false),
+            ScriptType::Int => ScriptValue::Int(0),
+            ScriptType::UInt => ScriptValue::UInt(0),
+            ScriptType::Float => ScriptValue::Float(0.0),
+            ScriptType::Double => ScriptValue::Double(0.0),
+            ScriptType::String => ScriptValue::String(String::new()),
+            ScriptType::Array(array_type) => ScriptValue::Array(ArrayValue::empty(array_type)),
+        }
+    }
+}
+
+/// A Rust type that can be passed to and from scripts, `#[derive(ScriptClass)]` and
+/// `#[script_methods]` use it to convert properties, arguments and return values.
+pub trait ScriptData: Sized {
+    const TYPE: ScriptType;
+
+    /// `None` if the value has another type.
+    fn from_script(value: ScriptValue) -> Option<Self>;
+
+    fn into_script(self) -> ScriptValue;
+}
+
+/// What a script method can return, a [`ScriptData`] or a `Result` of one. Errors are raised in
+/// the script like a failed call of a built-in object.
+pub trait ScriptReturn {
+    const TYPE: ScriptType;
+
+    fn into_script_result(self) -> Result<ScriptValue, String>;
+}
+
+impl ScriptData for () {
+    const TYPE: ScriptType = ScriptType::Void;
+
+    fn from_script(value: ScriptValue) -> Option<Self> {
+        matches!(value, ScriptValue::Void).then_some(())
+    }
+
+    fn into_script(self) -> ScriptValue {
+        ScriptValue::Void
+    }
+}
+
+macro_rules! script_data {
+    ($($ty:ty => $variant:ident),*) => {
+        $(
+            impl ScriptData for $ty {
+                const TYPE: ScriptType = ScriptType::$variant;
+
+                fn from_script(value: ScriptValue) -> Option<Self> {
+                    match value {
+                        ScriptValue::$variant(value) => Some(value),
+                        _ => None,
+                    }
+                }
+
+                fn into_script(self) -> ScriptValue {
+                    ScriptValue::$variant(self)
+                }
+            }
+        )*
+    };
+}
+
+script_data!(bool => Bool, i32 => Int, u32 => UInt, f32 => Float, f64 => Double, String => String);
+
+macro_rules! script_array {
+    ($($ty:ty => $variant:ident),*) => {
+        $(
+            impl ScriptData for Vec<$ty> {
+                const TYPE: ScriptType = ScriptType::Array(ArrayType::$variant);
+
+                fn from_script(value: ScriptValue) -> Option<Self> {
+                    match value {
+                        ScriptValue::Array(ArrayValue::$variant(values)) => Some(values),
+                        _ => None,
+                    }
+                }
+
+                fn into_script(self) -> ScriptValue {
+                    ScriptValue::Array(ArrayValue::$variant(self))
+                }
+            }
+        )*
+    };
+}
+
+script_array!(bool => Bool, i32 => Int, u32 => UInt, f32 => Float, f64 => Double);
+
+macro_rules! script_return {
+    ($($ty:ty),*) => {
+        $(
+            impl ScriptReturn for $ty {
+                const TYPE: ScriptType = <$ty as ScriptData>::TYPE;
+
+                fn into_script_result(self) -> Result<ScriptValue, String> {
+                    Ok(self.into_script())
+                }
+            }
+
+            impl<E: Display> ScriptReturn for Result<$ty, E> {
+                const TYPE: ScriptType = <$ty as ScriptData>::TYPE;
+
+                fn into_script_result(self) -> Result<ScriptValue, String> {
+                    self.map(ScriptData::into_script).map_err(|error| error.to_string())
+                }
+            }
+        )*
+    };
+}
+
+script_return!((), bool, i32, u32, f32, f64, String);
+script_return!(Vec<bool>, Vec<i32>, Vec<u32>, Vec<f32>, Vec<f64>);
+
+/// An object of the registered class `T` scripts can be handed, like a child object returned
+/// by a method. Methods and properties take and return it like other [`ScriptData`],
+/// `Option<ScriptRef<T>>` also takes and returns `Nothing`.
+pub struct ScriptRef<T>(Rc<Instance<T>>);
+
+impl<T: Scriptable> ScriptRef<T> {
+    const OBJECT_TYPE: &'static ObjectType = &ObjectType {
+        name: T::CLASS_NAME,
+        into_host: into_host::<T>,
+        from_host: from_host::<T>,
+    };
+
+    /// Creates an object with the members of the first registration of `T`.
+    pub fn new(value: T) -> Result<Self, VpxError> {
+        let (class, owner) = CLASSES
+            .with_borrow(|classes| {
+                classes.iter().flatten().find_map(|slot| {
+                    let class = Rc::clone(&slot.factory).as_any().downcast::<ClassData<T>>();
+                    Some((class.ok()?, slot.owner.clone()))
+                })
+            })
+            .ok_or(VpxError::UnregisteredClass(std::any::type_name::<T>()))?;
+        Ok(ScriptRef(Rc::new(Instance {
+            class,
+            owner,
+            refs: Cell::new(0),
+            value: RefCell::new(value),
+        })))
+    }
+
+    /// Panics while a script calls a member of the object that borrows it mutably.
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.0.value.borrow()
+    }
+
+    /// Panics while a script calls a member of the object.
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        self.0.value.borrow_mut()
+    }
+}
+
+impl<T> Clone for ScriptRef<T> {
+    fn clone(&self) -> Self {
+        ScriptRef(Rc::clone(&self.0))
+    }
+}
+
+impl<T> PartialEq for ScriptRef<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T> Debug for ScriptRef<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ScriptRef")
+            .field(&self.0.class.name)
+            .finish()
+    }
+}
+
+fn into_host<T: 'static>(instance: &Rc<dyn Any>) -> *mut c_void {
+    let Ok(instance) = Rc::clone(instance).downcast::<Instance<T>>() else {
+        return std::ptr::null_mut();
+    };
+    let refs = instance.refs.get();
+    instance.refs.set(refs + 1);
+    let object = Rc::into_raw(instance);
+    if refs > 0 {
+        // the host already holds a strong reference for its other refs
+        unsafe { Rc::decrement_strong_count(object) };
+    }
+    object as *mut c_void
+}
+
+unsafe fn from_host<T: 'static>(object: *mut c_void) -> Rc<dyn Any> {
+    let object = object as *const Instance<T>;
+    Rc::increment_strong_count(object);
+    Rc::<Instance<T>>::from_raw(object)
+}
+
+impl<T: Scriptable> ScriptData for ScriptRef<T> {
+    const TYPE: ScriptType = ScriptType::Object(Self::OBJECT_TYPE);
+
+    fn from_script(value: ScriptValue) -> Option<Self> {
+        match value {
+            ScriptValue::Object(object) => object.instance.downcast().ok().map(ScriptRef),
+            _ => None,
+        }
+    }
+
+    fn into_script(self) -> ScriptValue {
+        ScriptValue::Object(ScriptObject {
+            object_type: Self::OBJECT_TYPE,
+            instance: self.0,
+        })
+    }
+}
+
+impl<T: Scriptable> ScriptData for Option<ScriptRef<T>> {
+    const TYPE: ScriptType = <ScriptRef<T> as ScriptData>::TYPE;
+
+    fn from_script(value: ScriptValue) -> Option<Self> {
+        match value {
+            ScriptValue::Void => Some(None),
+            value => ScriptRef::from_script(value).map(Some),
+        }
+    }
+
+    fn into_script(self) -> ScriptValue {
+        self.map_or(ScriptValue::Void, ScriptData::into_script)
+    }
+}
+
+impl<T: Scriptable> ScriptReturn for ScriptRef<T> {
+    const TYPE: ScriptType = <Self as ScriptData>::TYPE;
+
+    fn into_script_result(self) -> Result<ScriptValue, String> {
+        Ok(self.into_script())
+    }
+}
+
+impl<T: Scriptable, E: Display> ScriptReturn for Result<ScriptRef<T>, E> {
+    const TYPE: ScriptType = <ScriptRef<T> as ScriptData>::TYPE;
+
+    fn into_script_result(self) -> Result<ScriptValue, String> {
+        self.map(ScriptData::into_script)
+            .map_err(|error| error.to_string())
+    }
+}
+
+impl<T: Scriptable> ScriptReturn for Option<ScriptRef<T>> {
+    const TYPE: ScriptType = <Self as ScriptData>::TYPE;
+
+    fn into_script_result(self) -> Result<ScriptValue, String> {
+        Ok(self.into_script())
+    }
+}
+
+/// A type scripts can create, usually derived with `#[derive(ScriptClass)]`. Register it with
+/// `api.register_script_class(T::script_class())`.
+pub trait Scriptable: Sized + 'static {
+    /// The name the class is registered with, scripts and the host know objects by it.
+    const CLASS_NAME: &'static CStr;
+
+    fn script_class() -> ScriptClass<Self>;
+}
+
+/// Adds the methods of an impl block to the class, implemented by `#[script_methods]`.
+pub trait ScriptMethods: Sized + 'static {
+    fn script_methods(class: ScriptClass<Self>) -> ScriptClass<Self>;
+}
+
+unsafe extern "C" fn release_string(me: *mut ScriptString) {
+    let string = std::mem::replace(&mut (*me).string, std::ptr::null());
+    if !string.is_null() {
+        drop(CString::from_raw(string as *mut c_char));
+    }
+}
+
+type MemberFn<T> = Box<dyn Fn(&mut T, &[ScriptValue]) -> Result<ScriptValue, String>>;
+
+struct Member<T> {
+    name: String,
+    args: Vec<ScriptType>,
+    ret: ScriptType,
+    call: MemberFn<T>,
+}
+
+/// A Rust type exposed to table scripts, describe its members and register it with
+/// `api.register_script_class(class)`. `#[derive(ScriptClass)]` and `#[script_methods]` build
+/// one from a struct and an impl block.
+///
+/// ```no_run
+/// # use vpinball_plugin_api::{ScriptClass, ScriptType, ScriptValue, VPXApi};
+/// # fn register(api: &dyn VPXApi) -> Result<(), vpinball_plugin_api::VpxError> {
+/// #[derive(Default)]
+/// struct Counter {
+///     count: i32,
+/// }
+///
+/// let class = ScriptClass::new("Counter", Counter::default)
+///     .method("Add", &[ScriptType::Int], ScriptType::Int, |counter, args| {
+///         if let ScriptValue::Int(amount) = args[0] {
+///             counter.count += amount;
+///         }
+///         ScriptValue::Int(counter.count)
+///     })
+///     .getter("Count", ScriptType::Int, |counter| ScriptValue::Int(counter.count));
+/// let counter = api.register_script_class(class)?;
+/// // Set counter = CreateObject("MyPlugin.Counter")
+/// counter.create_object_as("MyPlugin.Counter")?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct ScriptClass<T> {
+    name: String,
+    create: Box<dyn Fn() -> T>,
+    members: Vec<Member<T>>,
+}
+
+impl<T: 'static> ScriptClass<T> {
+    /// `create` makes a new object every time a script asks for one.
+    pub fn new(name: &str, create: impl Fn() -> T + 'static) -> Self {
+        Self {
+            name: name.to_string(),
+            create: Box::new(create),
+            members: Vec::new(),
+        }
+    }
+
+    /// Adds a method, the arguments are converted to `args` before `call` sees them and the
+    /// result is converted to `ret`.
+    pub fn method(
+        self,
+        name: &str,
+        args: &[ScriptType],
+        ret: ScriptType,
+        call: impl Fn(&mut T, &[ScriptValue]) -> ScriptValue + 'static,
+    ) -> Self {
+        self.try_method(name, args, ret, move |object, args| Ok(call(object, args)))
+    }
+
+    /// Like [`ScriptClass::method`], an error is raised in the script with the class and member
+    /// name, when the host can not raise it the error is logged and the script gets the
+    /// default value of `ret`.
+    pub fn try_method(
+        mut self,
+        name: &str,
+        args: &[ScriptType],
+        ret: ScriptType,
+        call: impl Fn(&mut T, &[ScriptValue]) -> Result<ScriptValue, String> + 'static,
+    ) -> Self {
+        self.members.push(Member {
+            name: name.to_string(),
+            args: args.to_vec(),
+            ret,
+            call: Box::new(call),
+        });
+        self
+    }
+
+    /// Adds a readable property.
+    pub fn getter(
+        self,
+        name: &str,
+        ty: ScriptType,
+        get: impl Fn(&T) -> ScriptValue + 'static,
+    ) -> Self {
+        self.method(name, &[], ty, move |object, _| get(object))
+    }
+
+    /// Adds a writable property.
+    pub fn setter(
+        self,
+        name: &str,
+        ty: ScriptType,
+        set: impl Fn(&mut T, ScriptValue) + 'static,
+    ) -> Self {
+        self.try_setter(name, ty, move |object, value| {
+            set(object, value);
+            Ok(())
+        })
+    }
+
+    /// Like [`ScriptClass::setter`], an error is reported like the errors of
+    /// [`ScriptClass::try_method`].
+    pub fn try_setter(
+        self,
+        name: &str,
+        ty: ScriptType,
+        set: impl Fn(&mut T, ScriptValue) -> Result<(), String> + 'static,
+    ) -> Self {
+        self.try_method(name, &[ty], ScriptType::Void, move |object, args| {
+            set(object, args[0].clone()).map(|()| ScriptValue::Void)
+        })
+    }
+
+    /// Builds the class definition the host expects, used by `register_script_class`.
+    pub fn into_definition(self) -> Result<ScriptClassDefinition, VpxError> {
+        let class_name = CString::new(self.name.as_str())?;
+        let mut members = Vec::with_capacity(BUILTIN_MEMBERS.len() + self.members.len());
+        for name in BUILTIN_MEMBERS {
+            members.push(member_def::<T>(name.to_owned(), ScriptType::Void, &[]));
+        }
+        for member in &self.members {
+            if member.args.len() > PSC_CALL_MAX_ARG_COUNT as usize {
+                return Err(VpxError::InvalidValue("script method arguments"));
+            }
+            let name = CString::new(member.name.as_str())?;
+            members.push(member_def::<T>(name, member.ret, &member.args));
+        }
+        let mut arrays = Vec::new();
+        for member in &self.members {
+            for ty in member.args.iter().chain([&member.ret]) {
+                if let ScriptType::Array(array_type) = *ty {
+                    if !arrays.contains(&array_type) {
+                        arrays.push(array_type);
+                    }
+                }
+            }
+        }
+        let factory: Rc<dyn ObjectFactory> = Rc::new(ClassData {
+            name: self.name.clone(),
+            create: self.create,
+            members: self.members,
+        });
+        Ok(ScriptClassDefinition {
+            name: self.name,
+            class_name,
+            members,
+            arrays,
+            factory,
+        })
+    }
+}
+
+/// Reference counting members the host calls to manage the lifetime of objects, they come
+/// before the members of the class.
+const BUILTIN_MEMBERS: [&CStr; 2] = [c"AddRef", c"Release"];
+
+fn member_def<T: 'static>(
+    name: CString,
+    ret: ScriptType,
+    args: &[ScriptType],
+) -> ScriptClassMemberDef {
+    let mut call_arg_type = [ScriptType::Void.type_name(); PSC_CALL_MAX_ARG_COUNT as usize];
+    for (slot, arg) in call_arg_type.iter_mut().zip(args) {
+        *slot = arg.type_name();
+    }
+    ScriptClassMemberDef {
+        // the host keeps the definition for as long as it runs
+        name: ScriptTypeNameDef {
+            name: name.into_raw(),
+            id: 0,
+        },
+        type_: ret.type_name(),
+        nArgs: args.len() as u32,
+        callArgType: call_arg_type,
+        Call: Some(call_member::<T>),
+    }
+}
+
+/// Allocates a definition with the members following it, the host keeps a pointer to it until
+/// it exits so it is never freed.
+fn alloc_class_def(
+    name: CString,
+    create_object: CreateObjectFn,
+    members: Vec<ScriptClassMemberDef>,
+) -> *mut ScriptClassDef {
+    let layout = std::alloc::Layout::new::<ScriptClassDef>()
+        .extend(std::alloc::Layout::array::<ScriptClassMemberDef>(members.len()).unwrap())
+        .unwrap()
+        .0
+        .pad_to_align();
+    unsafe {
+        let def = std::alloc::alloc_zeroed(layout) as *mut ScriptClassDef;
+        if def.is_null() {
+            std::alloc::handle_alloc_error(layout);
+        }
+        def.write(ScriptClassDef {
+            name: ScriptTypeNameDef {
+                name: name.into_raw(),
+                id: 0,
+            },
+            CreateObject: Some(create_object),
+            nMembers: members.len() as u32,
+            members: __IncompleteArrayField::new(),
+        });
+        let first = std::ptr::addr_of_mut!((*def).members) as *mut ScriptClassMemberDef;
+        for (index, member) in members.into_iter().enumerate() {
+            first.add(index).write(member);
+        }
+        def
+    }
+}
+
+/// A class ready to be registered, see [`ScriptClass::into_definition`].
+pub struct ScriptClassDefinition {
+    name: String,
+    class_name: CString,
+    members: Vec<ScriptClassMemberDef>,
+    /// Array types the members take or return.
+    arrays: Vec<ArrayType>,
+    factory: Rc<dyn ObjectFactory>,
+}
+
+struct ClassData<T> {
+    name: String,
+    create: Box<dyn Fn() -> T>,
+    members: Vec<Member<T>>,
+}
+
+/// Creates objects of a registered class without knowing its type.
+trait ObjectFactory {
+    fn create_object(self: Rc<Self>, owner: Owner) -> *mut c_void;
+
+    fn as_any(self: Rc<Self>) -> Rc<dyn Any>;
+}
+
+impl<T: 'static> ObjectFactory for ClassData<T> {
+    fn create_object(self: Rc<Self>, owner: Owner) -> *mut c_void {
+        let value = (self.create)();
+        let object = Rc::new(Instance {
+            class: self,
+            owner,
+            refs: Cell::new(1),
+            value: RefCell::new(value),
+        });
+        Rc::into_raw(object) as *mut c_void
+    }
+
+    fn as_any(self: Rc<Self>) -> Rc<dyn Any> {
+        self
+    }
+}
+
+type CreateObjectFn = unsafe extern "C" fn() -> *mut c_void;
+
+/// How many classes can be registered at once, `CreateObject` gets no arguments so every
+/// registration needs its own function.
+const MAX_CLASSES: usize = 16;
+
+macro_rules! create_object_fns {
+    ($($slot:literal)*) => {
+        [$(create_object::<$slot>),*]
+    };
+}
+
+static CREATE_OBJECT: [CreateObjectFn; MAX_CLASSES] =
+    create_object_fns!(0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15);
+
+/// The plugin that registered a class, its objects run script calls through its panic state
+/// and raise errors through its host api.
+#[derive(Clone)]
+struct Owner {
+    panic: Arc<PanicState>,
+    /// Stays valid after the plugin unloads, the host keeps its ScriptablePlugin until it exits.
+    api: *const ScriptablePluginAPI,
+}
+
+impl Owner {
+    /// Raises the error in the script that made the call, logs it when the host can not.
+    fn raise(&self, context: &str, message: &str) {
+        let on_error = unsafe { self.api.as_ref() }.and_then(|api| api.OnError);
+        let Some(on_error) = on_error else {
+            error!("Script error in {context}: {message}");
+            return;
+        };
+        info!("Raising script error in {context}: {message}");
+        // passed as the format, so escape it instead of passing variadic arguments
+        let format = format!("{context}: {message}")
+            .replace('\0', "")
+            .replace('%', "%%");
+        let format = CString::new(format).unwrap_or_default();
+        unsafe { on_error(PSC_ERR_FAIL, format.as_ptr()) };
+    }
+}
+
+/// A registered class in its `CreateObject` slot.
+struct ClassSlot {
+    factory: Rc<dyn ObjectFactory>,
+    owner: Owner,
+}
+
+thread_local! {
+    /// Classes that can be created right now by slot, scripts only run on the main thread.
+    static CLASSES: RefCell<[Option<ClassSlot>; MAX_CLASSES]> =
+        const { RefCell::new([const { None }; MAX_CLASSES]) };
+}
+
+/// An object handed out to a script, it keeps its class alive so it keeps working when the
+/// class is unregistered.
+///
+/// The host holds a single strong reference for all of its `refs`, calls hold another one so
+/// a `Release` during a call does not free the object under it.
+struct Instance<T> {
+    class: Rc<ClassData<T>>,
+    owner: Owner,
+    refs: Cell<u32>,
+    value: RefCell<T>,
+}
+
+unsafe extern "C" fn create_object<const SLOT: usize>() -> *mut c_void {
+    let slot = CLASSES.with_borrow(|classes| {
+        let slot = classes[SLOT].as_ref()?;
+        Some((Rc::clone(&slot.factory), slot.owner.clone()))
+    });
+    let Some((class, owner)) = slot else {
+        warn!("Script asked for an object of an unregistered class");
+        return std::ptr::null_mut();
+    };
+    owner
+        .panic
+        .guard("CreateObject", || class.create_object(owner.clone()))
+        .unwrap_or(std::ptr::null_mut())
+}
+
+unsafe extern "C" fn call_member<T: 'static>(
+    me: *mut c_void,
+    member_index: c_int,
+    args: *mut ScriptVariant,
+    ret: *mut ScriptVariant,
+) {
+    let object_ptr = me as *const Instance<T>;
+    if object_ptr.is_null() {
+        warn!("Script member called without an object");
+        return;
+    }
+    let owner = (*object_ptr).owner.clone();
+    // the last release drops the value, which runs plugin code as well
+    owner.panic.guard("script call", || {
+        call_instance::<T>(object_ptr, &owner, member_index, args, ret)
+    });
+}
+
+unsafe fn call_instance<T: 'static>(
+    object_ptr: *const Instance<T>,
+    owner: &Owner,
+    member_index: c_int,
+    args: *mut ScriptVariant,
+    ret: *mut ScriptVariant,
+) {
+    // kept alive until the call returns, whatever the host releases meanwhile
+    Rc::increment_strong_count(object_ptr);
+    let object = Rc::from_raw(object_ptr);
+    match member_index {
+        0 => object.refs.set(object.refs.get() + 1),
+        1 => match object.refs.get() {
+            0 => warn!("{} released more often than referenced", object.class.name),
+            1 => {
+                object.refs.set(0);
+                // the reference of the host, the object is freed when `object` is dropped
+                Rc::decrement_strong_count(object_ptr);
+            }
+            refs => object.refs.set(refs - 1),
+        },
+        index => {
+            let member_index = index as usize - BUILTIN_MEMBERS.len();
+            let Some(member) = object.class.members.get(member_index) else {
+                warn!("Script called unknown member {index}");
+                return;
+            };
+            let values: Vec<ScriptValue> = member
+                .args
+                .iter()
+                .enumerate()
+                .map(|(i, ty)| ScriptValue::read(*ty, &*args.add(i)))
+                .collect();
+            let context = format!("{}.{}", object.class.name, member.name);
+            let Ok(mut value) = object.value.try_borrow_mut() else {
+                owner.raise(&context, "the object is busy in another call");
+                return;
+            };
+            // borrowing it in the member would panic, which is the script's mistake
+            let passes_itself = values.iter().any(|value| {
+                matches!(value, ScriptValue::Object(other)
+                    if std::ptr::addr_eq(Rc::as_ptr(&other.instance), object_ptr))
+            });
+            let result = if passes_itself {
+                Err("the object is passed to its own member".to_string())
+            } else {
+                owner
+                    .panic
+                    .guard(&context, || (member.call)(&mut value, &values))
+                    .unwrap_or_else(|| Ok(ScriptValue::default_of(member.ret)))
+            };
+            let result = result.unwrap_or_else(|message| {
+                owner.raise(&context, &message);
+                ScriptValue::default_of(member.ret)
+            });
+            drop(value);
+            if let Some(ret) = ret.as_mut() {
+                if let ScriptType::Object(_) = member.ret {
+                    // `Nothing` unless an object is written
+                    ret.vObject = std::ptr::null_mut();
+                }
+                let ty = result.script_type();
+                let result = result.convert(member.ret).unwrap_or_else(|| {
+                    warn!(
+                        "{} returned {ty:?} instead of {:?}",
+                        member.name, member.ret
+                    );
+                    ScriptValue::default_of(member.ret)
+                });
+                result.write(ret);
+            }
+        }
+    }
+}
+
+struct Registration {
+    id: u64,
+    slot: usize,
+    prog_ids: Vec<CString>,
+}
+
+/// Talks to the host ScriptablePlugin, shared with the handles that are given out.
+pub(crate) struct Scripting {
+    /// Null while the plugin is not loaded or the host has no ScriptablePlugin.
+    api: Cell<*const ScriptablePluginAPI>,
+    panic: Arc<PanicState>,
+    next_id: Cell<u64>,
+    /// Registered classes with the prog ids that create them.
+    classes: RefCell<Vec<Registration>>,
+    /// Array types already registered with the host, which keeps them until it exits.
+    arrays: RefCell<Vec<ArrayType>>,
+}
+
+impl Scripting {
+    pub(crate) fn new(panic: Arc<PanicState>) -> Self {
+        Self {
+            api: Cell::new(std::ptr::null()),
+            panic,
+            next_id: Cell::new(0),
+            classes: RefCell::default(),
+            arrays: RefCell::default(),
+        }
+    }
+
+    pub(crate) fn set_api(&self, api: *const ScriptablePluginAPI) {
+        self.api.set(api);
+        self.arrays.borrow_mut().clear();
+    }
+
+    fn api(&self) -> Result<&ScriptablePluginAPI, VpxError> {
+        unsafe { self.api.get().as_ref() }.ok_or(VpxError::NoData("the scriptable plugin api"))
+    }
+
+    pub(crate) fn register(
+        self: &Rc<Self>,
+        class: ScriptClassDefinition,
+    ) -> Result<ScriptClassHandle, VpxError> {
+        let api = self.api()?;
+        let register = crate::require(api.RegisterScriptClass, "RegisterScriptClass")?;
+        let submit = crate::require(api.SubmitTypeLibrary, "SubmitTypeLibrary")?;
+        self.register_arrays(api, &class.arrays)?;
+        let slot = CLASSES.with_borrow_mut(|classes| {
+            let slot = classes.iter().position(Option::is_none)?;
+            classes[slot] = Some(ClassSlot {
+                factory: class.factory,
+                owner: Owner {
+                    panic: Arc::clone(&self.panic),
+                    api,
+                },
+            });
+            Some(slot)
+        });
+        let slot = slot.ok_or(VpxError::InvalidValue("number of script classes"))?;
+        info!("Registering script class {}", class.name);
+        let def = alloc_class_def(class.class_name, CREATE_OBJECT[slot], class.members);
+        unsafe {
+            register(def);
+            submit();
+        }
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        self.classes.borrow_mut().push(Registration {
+            id,
+            slot,
+            prog_ids: Vec::new(),
+        });
+        Ok(ScriptClassHandle {
+            scripting: Rc::downgrade(self),
+            def,
+            id,
+        })
+    }
+
+    /// Array types have to be known to the host before a class uses them.
+    fn register_arrays(
+        &self,
+        api: &ScriptablePluginAPI,
+        array_types: &[ArrayType],
+    ) -> Result<(), VpxError> {
+        let mut registered = self.arrays.borrow_mut();
+        for &array_type in array_types {
+            if registered.contains(&array_type) {
+                continue;
+            }
+            let register = crate::require(api.RegisterScriptArrayType, "RegisterScriptArrayType")?;
+            // the host keeps the definition for as long as it runs
+            let def = Box::into_raw(Box::new(array_type.definition()));
+            unsafe { register(def) };
+            registered.push(array_type);
+        }
+        Ok(())
+    }
+
+    fn create_object_as(
+        &self,
+        id: u64,
+        def: *const ScriptClassDef,
+        prog_id: &str,
+    ) -> Result<(), VpxError> {
+        let set_override =
+            crate::require(self.api()?.SetCOMObjectOverride, "SetCOMObjectOverride")?;
+        let mut classes = self.classes.borrow_mut();
+        let registration = classes
+            .iter_mut()
+            .find(|registration| registration.id == id)
+            .ok_or(VpxError::Unloaded)?;
+        let prog_id = CString::new(prog_id)?;
+        unsafe { set_override(prog_id.as_ptr(), def) };
+        registration.prog_ids.push(prog_id);
+        Ok(())
+    }
+
+    fn unregister(&self, id: u64) {
+        let registration = {
+            let mut classes = self.classes.borrow_mut();
+            let Some(index) = classes
+                .iter()
+                .position(|registration| registration.id == id)
+            else {
+                return;
+            };
+            classes.remove(index)
+        };
+        CLASSES.with_borrow_mut(|classes| classes[registration.slot] = None);
+        let set_override = self.api().ok().and_then(|api| api.SetCOMObjectOverride);
+        if let Some(set_override) = set_override {
+            for prog_id in registration.prog_ids {
+                unsafe { set_override(prog_id.as_ptr(), std::ptr::null()) };
+            }
+        }
+    }
+
+    /// Stops handing out objects on unload, objects scripts already hold keep working.
+    pub(crate) fn release_all(&self) {
+        let ids: Vec<u64> = self
+            .classes
+            .borrow()
+            .iter()
+            .map(|registration| registration.id)
+            .collect();
+        for id in ids {
+            self.unregister(id);
+        }
+    }
+}
+
+/// A registered script class, scripts can no longer create objects once it is dropped or the
+/// plugin unloads. The same type can be registered more than once.
+pub struct ScriptClassHandle {
+    scripting: Weak<Scripting>,
+    def: *const ScriptClassDef,
+    id: u64,
+}
+
+impl ScriptClassHandle {
+    /// Lets scripts create objects with `CreateObject(prog_id)`, e.g. `"MyPlugin.Counter"`.
+    pub fn create_object_as(&self, prog_id: &str) -> Result<(), VpxError> {
+        let scripting = self.scripting.upgrade().ok_or(VpxError::Unloaded)?;
+        scripting.create_object_as(self.id, self.def, prog_id)
+    }
+}
+
+impl Drop for ScriptClassHandle {
+    fn drop(&mut self) {
+        if let Some(scripting) = self.scripting.upgrade() {
+            scripting.unregister(self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{wrapped_api, TestMsgPluginAPI};
+    use crate::VPXApi;
+
+    #[derive(Default)]
+    struct Thing {
+        name: String,
+    }
+
+    #[test]
+    fn test_script_creates_and_calls_object() {
+        TestMsgPluginAPI::enable_scripting();
+        let api = wrapped_api();
+        let api: &dyn VPXApi = &*api;
+
+        let class = ScriptClass::new("Thing", Thing::default)
+            .method(
+                "Add",
+                &[ScriptType::Int, ScriptType::Int],
+                ScriptType::Double,
+                |_, args| match args {
+                    [ScriptValue::Int(a), ScriptValue::Int(b)] => ScriptValue::Int(a + b),
+                    _ => ScriptValue::Void,
+                },
+            )
+            .getter("Name", ScriptType::String, |thing| {
+                ScriptValue::String(thing.name.clone())
+            })
+            .setter("Name", ScriptType::String, |thing, value| {
+                if let ScriptValue::String(name) = value {
+                    thing.name = name;
+                }
+            });
+        let handle = api.register_script_class(class).unwrap();
+        handle.create_object_as("OurPlugin.Thing").unwrap();
+
+        let thing = TestMsgPluginAPI::create_script_object("OurPlugin.Thing").unwrap();
+        let sum = thing.call("Add", &[ScriptValue::Int(2), ScriptValue::Int(3)]);
+        assert_eq!(sum, ScriptValue::Double(5.0));
+        thing.call("Name", &[ScriptValue::String("Ball".to_string())]);
+        assert_eq!(
+            thing.call("Name", &[]),
+            ScriptValue::String("Ball".to_string())
+        );
+
+        // the same type registered again is a class of its own
+        let other = api
+            .register_script_class(ScriptClass::new("OtherThing", Thing::default))
+            .unwrap();
+        other.create_object_as("OurPlugin.OtherThing").unwrap();
+
+        // objects keep working, new ones can not be created
+        drop(handle);
+        assert!(TestMsgPluginAPI::create_script_object("OurPlugin.Thing").is_none());
+        assert!(TestMsgPluginAPI::create_script_object("OurPlugin.OtherThing").is_some());
+        assert_eq!(
+            thing.call("Name", &[]),
+            ScriptValue::String("Ball".to_string())
+        );
+    }
+
+    #[derive(crate::ScriptClass, Default)]
+    #[script(name = "Counter")]
+    struct Counter {
+        #[script]
+        step: i32,
+        #[script(get)]
+        count: i32,
+        #[script]
+        weights: Vec<f64>,
+    }
+
+    #[crate::script_methods]
+    impl Counter {
+        fn add(&mut self, times: u32) -> i32 {
+            self.count += self.step * times as i32;
+            self.count
+        }
+
+        #[script(name = "Label")]
+        fn describe(&self, prefix: String) -> Result<String, String> {
+            if prefix.is_empty() {
+                return Err("empty prefix".to_string());
+            }
+            Ok(format!("{prefix}{}", self.count))
+        }
+
+        /// Adds each step in turn and returns the counts in between.
+        fn add_each(&mut self, steps: Vec<i32>) -> Vec<i32> {
+            steps
+                .into_iter()
+                .map(|step| {
+                    self.count += step;
+                    self.count
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_derived_class() {
+        TestMsgPluginAPI::enable_scripting();
+        let api = wrapped_api();
+        let api: &dyn VPXApi = &*api;
+
+        let handle = api.register_script_class(Counter::script_class()).unwrap();
+        handle.create_object_as("OurPlugin.Counter").unwrap();
+        let counter = TestMsgPluginAPI::create_script_object("OurPlugin.Counter").unwrap();
+        counter.call("Step", &[ScriptValue::Int(2)]);
+        assert_eq!(
+            counter.call("Add", &[ScriptValue::UInt(3)]),
+            ScriptValue::Int(6)
+        );
+        assert_eq!(counter.call("Count", &[]), ScriptValue::Int(6));
+        assert_eq!(
+            counter.call("Label", &[ScriptValue::String("count ".to_string())]),
+            ScriptValue::String("count 6".to_string())
+        );
+        // errors are raised in the script, which gets an empty value
+        assert_eq!(
+            counter.call("Label", &[ScriptValue::String(String::new())]),
+            ScriptValue::String(String::new())
+        );
+        assert_eq!(
+            TestMsgPluginAPI::take_script_errors(),
+            vec!["Counter.Label: empty prefix".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_arrays() {
+        TestMsgPluginAPI::enable_scripting();
+        let api = wrapped_api();
+        let api: &dyn VPXApi = &*api;
+
+        let handle = api.register_script_class(Counter::script_class()).unwrap();
+        handle.create_object_as("OurPlugin.Counter").unwrap();
+        // registered once, before the class that uses them
+        assert_eq!(
+            TestMsgPluginAPI::script_array_types(),
+            vec!["DoubleArray".to_string(), "IntArray".to_string()]
+        );
+        let counter = TestMsgPluginAPI::create_script_object("OurPlugin.Counter").unwrap();
+        assert_eq!(
+            counter.call(
+                "AddEach",
+                &[ScriptValue::Array(ArrayValue::Int(vec![1, -3, 10]))]
+            ),
+            ScriptValue::Array(ArrayValue::Int(vec![1, -2, 8]))
+        );
+        assert_eq!(
+            counter.call("AddEach", &[ScriptValue::Array(ArrayValue::Int(vec![]))]),
+            ScriptValue::Array(ArrayValue::Int(vec![]))
+        );
+        let weights = ScriptValue::Array(ArrayValue::Double(vec![0.5, 1.0, 2.5]));
+        counter.call("Weights", std::slice::from_ref(&weights));
+        assert_eq!(counter.call("Weights", &[]), weights);
+        assert!(TestMsgPluginAPI::take_script_errors().is_empty());
+    }
+
+    thread_local! {
+        static NODES_DROPPED: Cell<u32> = const { Cell::new(0) };
+    }
+
+    #[derive(crate::ScriptClass, Default)]
+    #[script(name = "Node")]
+    struct Node {
+        #[script]
+        value: i32,
+        #[script]
+        next: Option<ScriptRef<Node>>,
+    }
+
+    #[crate::script_methods]
+    impl Node {
+        fn spawn(&self) -> Result<ScriptRef<Node>, VpxError> {
+            ScriptRef::new(Node {
+                value: self.value + 1,
+                next: None,
+            })
+        }
+
+        fn sum(&self, other: ScriptRef<Node>) -> i32 {
+            self.value + other.borrow().value
+        }
+    }
+
+    impl Drop for Node {
+        fn drop(&mut self) {
+            NODES_DROPPED.set(NODES_DROPPED.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_pass_objects() {
+        TestMsgPluginAPI::enable_scripting();
+        let api = wrapped_api();
+        let api: &dyn VPXApi = &*api;
+
+        assert_eq!(
+            ScriptRef::new(Node::default()).unwrap_err(),
+            VpxError::UnregisteredClass(std::any::type_name::<Node>())
+        );
+        let dropped = NODES_DROPPED.get();
+        let handle = api.register_script_class(Node::script_class()).unwrap();
+        handle.create_object_as("OurPlugin.Node").unwrap();
+        let first = TestMsgPluginAPI::create_script_object("OurPlugin.Node").unwrap();
+        first.call("Value", &[ScriptValue::Int(1)]);
+        let second = first.call_object("Spawn", &[]).unwrap();
+        assert_eq!(second.call("Value", &[]), ScriptValue::Int(2));
+        assert_eq!(first.call_with_object("Sum", &second), ScriptValue::Int(3));
+        // borrowing the object in its own call would panic, the script gets 0 instead
+        assert_eq!(first.call_with_object("Sum", &first), ScriptValue::Int(0));
+
+        assert!(first.call_object("Next", &[]).is_none());
+        first.call_with_object("Next", &second);
+        drop(second);
+        let next = first.call_object("Next", &[]).unwrap();
+        assert_eq!(next.call("Value", &[]), ScriptValue::Int(2));
+        assert_eq!(NODES_DROPPED.get(), dropped);
+        drop(next);
+        drop(first);
+        assert_eq!(NODES_DROPPED.get(), dropped + 2);
+    }
+
+    #[derive(Default)]
+    struct Fragile {
+        pokes: i32,
+    }
+
+    impl Drop for Fragile {
+        fn drop(&mut self) {
+            if self.pokes > 0 {
+                panic!("dropped after a poke");
+            }
+        }
+    }
+
+    #[test]
+    fn test_panic_disables_scripts() {
+        TestMsgPluginAPI::enable_scripting();
+        let api = wrapped_api();
+
+        let class = ScriptClass::new("Fragile", Fragile::default).method(
+            "Poke",
+            &[],
+            ScriptType::Int,
+            |fragile, _| {
+                fragile.pokes += 1;
+                ScriptValue::Int(fragile.pokes)
+            },
+        );
+        let dyn_api: &dyn VPXApi = &*api;
+        let handle = dyn_api.register_script_class(class).unwrap();
+        handle.create_object_as("OurPlugin.Fragile").unwrap();
+        let first = TestMsgPluginAPI::create_script_object("OurPlugin.Fragile").unwrap();
+        let second = TestMsgPluginAPI::create_script_object("OurPlugin.Fragile").unwrap();
+        assert_eq!(first.call("Poke", &[]), ScriptValue::Int(1));
+
+        // the last release drops the value, its panic must not unwind into the host
+        drop(first);
+        assert!(api.panic.is_disabled());
+        assert_eq!(second.call("Poke", &[]), ScriptValue::Int(0));
+        assert!(TestMsgPluginAPI::create_script_object("OurPlugin.Fragile").is_none());
+    }
+
+    #[test]
+    fn test_convert() {
+        assert_eq!(
+            ScriptValue::Int(3).convert(ScriptType::Double),
+            Some(ScriptValue::Double(3.0))
+        );
+        assert_eq!(ScriptValue::Double(2.5).convert(ScriptType::Int), None);
+        assert_eq!(
+            ScriptValue::String("a".to_string()).convert(ScriptType::Int),
+            None
+        );
+    }
+}
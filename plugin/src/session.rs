@@ -0,0 +1,188 @@
+use crate::handle::ApiHandle;
+use crate::messages::{GameEnd, GameStart, PrepareFrame};
+use crate::{Subscription, TableInfo, VPXApi, VpxError};
+use log::warn;
+use std::cell::{Ref, RefCell, RefMut};
+use std::rc::Rc;
+
+/// State that only makes sense for a single game, see [`GameSession`].
+pub trait GameState: Sized + 'static {
+    /// Called when a game starts, the table info is captured right before.
+    fn start(api: &dyn VPXApi, table: &TableInfo) -> Self;
+
+    /// Called before each frame is prepared for rendering.
+    fn prepare_frame(&mut self, api: &dyn VPXApi) {}
+
+    /// Called when the game ends, right before the state is dropped. The in-game functions of
+    /// the api still work.
+    fn end(&mut self, api: &dyn VPXApi) {}
+}
+
+struct Game<S> {
+    table: TableInfo,
+    state: S,
+}
+
+type Current<S> = Rc<RefCell<Option<Game<S>>>>;
+
+/// Creates a new `S` for every game and drops it when the game ends, so nothing carries over
+/// from one table to the next. Create it with `api.game_session::<S>()`.
+///
+/// The plugin hooks run after the session callbacks, so the state already exists in
+/// `on_game_start` but is gone in `on_game_end`, use [`GameState::end`] for that.
+pub struct GameSession<S> {
+    current: Current<S>,
+    _subscriptions: Vec<Subscription>,
+}
+
+impl<S: GameState> GameSession<S> {
+    pub(crate) fn new(api: &dyn VPXApi) -> Result<Self, VpxError> {
+        let current: Current<S> = Rc::new(RefCell::new(None));
+        let handle = api.handle();
+        let start = {
+            let (handle, current) = (handle.clone(), Rc::clone(&current));
+            api.subscribe::<GameStart>(move |_| with_api(&handle, |api| start(api, &current)))?
+        };
+        let frame = {
+            let (handle, current) = (handle.clone(), Rc::clone(&current));
+            api.subscribe::<PrepareFrame>(move |_| {
+                // taken out for the call like in `end`, the session is not borrowed meanwhile
+                let Some(mut game) = current.take() else {
+                    return;
+                };
+                with_api(&handle, |api| game.state.prepare_frame(api));
+                // unless the game ended or restarted during the call
+                let mut slot = current.borrow_mut();
+                if slot.is_none() {
+                    *slot = Some(game);
+                }
+            })?
+        };
+        let end = {
+            let current = Rc::clone(&current);
+            api.subscribe::<GameEnd>(move |_| {
+                // taken out first so `end` can use the session freely
+                let game = current.take();
+                if let Some(mut game) = game {
+                    with_api(&handle, |api| game.state.end(api));
+                }
+            })?
+        };
+        Ok(Self {
+            current,
+            _subscriptions: vec![start, frame, end],
+        })
+    }
+
+    /// Whether a game is running.
+    pub fn is_active(&self) -> bool {
+        self.current.borrow().is_some()
+    }
+
+    /// Table of the running game, as it was when the game started.
+    pub fn table(&self) -> Option<Ref<'_, TableInfo>> {
+        Ref::filter_map(self.current.borrow(), |game| {
+            game.as_ref().map(|g| &g.table)
+        })
+        .ok()
+    }
+
+    /// State of the running game, do not hold on to it across host callbacks. `None` while
+    /// a [`GameState`] callback runs, which gets the state itself.
+    pub fn state(&self) -> Option<RefMut<'_, S>> {
+        RefMut::filter_map(self.current.borrow_mut(), |game| {
+            game.as_mut().map(|g| &mut g.state)
+        })
+        .ok()
+    }
+}
+
+fn start<S: GameState>(api: &dyn VPXApi, current: &Current<S>) {
+    let table = api.get_table_info().unwrap_or_else(|error| {
+        warn!("Starting game session without table info: {error}");
+        TableInfo::default()
+    });
+    let state = S::start(api, &table);
+    current.replace(Some(Game { table, state }));
+}
+
+fn with_api(handle: &ApiHandle, f: impl FnOnce(&dyn VPXApi)) {
+    if let Err(error) = handle.with_api(f) {
+        warn!("Game session callback skipped: {error}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{wrapped_api, TestMsgPluginAPI, TestVPXPluginAPI};
+    use crate::Message;
+
+    struct Frames {
+        count: u32,
+    }
+
+    impl GameState for Frames {
+        fn start(_api: &dyn VPXApi, table: &TableInfo) -> Self {
+            assert_eq!(table.path, "");
+            Self { count: 0 }
+        }
+
+        fn prepare_frame(&mut self, _api: &dyn VPXApi) {
+            self.count += 1;
+        }
+    }
+
+    #[test]
+    fn test_state_resets_between_games() {
+        let api = wrapped_api();
+        let api: &dyn VPXApi = &*api;
+
+        let session = api.game_session::<Frames>().unwrap();
+        assert!(!session.is_active());
+        let broadcast = |namespace, name| {
+            let msg_id = api.get_msg_id(namespace, name).unwrap();
+            TestMsgPluginAPI::broadcast(msg_id.raw());
+        };
+
+        broadcast(GameStart::NAMESPACE, GameStart::NAME);
+        broadcast(PrepareFrame::NAMESPACE, PrepareFrame::NAME);
+        broadcast(PrepareFrame::NAMESPACE, PrepareFrame::NAME);
+        assert_eq!(session.state().unwrap().count, 2);
+        assert_eq!(session.table().unwrap().path, "");
+
+        broadcast(GameEnd::NAMESPACE, GameEnd::NAME);
+        assert!(session.state().is_none());
+        broadcast(GameStart::NAMESPACE, GameStart::NAME);
+        assert_eq!(session.state().unwrap().count, 0);
+    }
+
+    struct Table {
+        width: f32,
+    }
+
+    impl GameState for Table {
+        fn start(_api: &dyn VPXApi, table: &TableInfo) -> Self {
+            Self {
+                width: table.tableWidth,
+            }
+        }
+    }
+
+    #[test]
+    fn test_start_with_table_info() {
+        TestVPXPluginAPI::set_table_info("tables/attack.vpx", 952.0, 2162.0);
+        let api = wrapped_api();
+        let api: &dyn VPXApi = &*api;
+
+        let session = api.game_session::<Table>().unwrap();
+        let msg_id = api
+            .get_msg_id(GameStart::NAMESPACE, GameStart::NAME)
+            .unwrap();
+        TestMsgPluginAPI::broadcast(msg_id.raw());
+        assert_eq!(session.state().unwrap().width, 952.0);
+        let table = session.table().unwrap();
+        assert_eq!(table.path, "tables/attack.vpx");
+        assert_eq!(table.tableHeight, 2162.0);
+    }
+}
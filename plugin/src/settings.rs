@@ -0,0 +1,118 @@
+use crate::bindings;
+use crate::error::{require, VpxError};
+use std::ffi::{c_char, c_uint, CStr, CString};
+use std::fmt::{Display, Formatter};
+
+/// Initial size of the buffer the host writes the setting value into.
+const INITIAL_BUFFER_SIZE: usize = 256;
+/// Values longer than this are reported as [`SettingError::TooLong`].
+const MAX_BUFFER_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SettingError {
+    /// The namespace or name contains a NUL byte
+    InvalidName,
+    /// The value is not valid UTF-8
+    NotUtf8,
+    /// The value did not fit in the largest buffer we are willing to allocate
+    TooLong,
+    /// The value could not be parsed into the requested type
+    Parse { value: String, error: String },
+    /// The host api failed
+    Api(VpxError),
+}
+
+impl Display for SettingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SettingError::InvalidName => write!(f, "setting name contains a NUL byte"),
+            SettingError::NotUtf8 => write!(f, "setting value is not valid UTF-8"),
+            SettingError::TooLong => {
+                write!(f, "setting value is longer than {MAX_BUFFER_SIZE} bytes")
+            }
+            SettingError::Parse { value, error } => {
+                write!(f, "failed to parse setting value \"{value}\": {error}")
+            }
+            SettingError::Api(error) => write!(f, "{error}"),
+        }
+    }
+}
+
+impl std::error::Error for SettingError {}
+
+impl From<VpxError> for SettingError {
+    fn from(error: VpxError) -> Self {
+        SettingError::Api(error)
+    }
+}
+
+/// Reads a setting through `GetSetting`, growing the buffer until the value fits.
+///
+/// The host writes an empty string for missing keys, which we report as `None`.
+pub(crate) fn read_setting(
+    msg: *mut bindings::MsgPluginAPI,
+    name_space: &str,
+    name: &str,
+) -> Result<Option<String>, SettingError> {
+    let name_space_c = CString::new(name_space).map_err(|_| SettingError::InvalidName)?;
+    let name_c = CString::new(name).map_err(|_| SettingError::InvalidName)?;
+    let get_setting = require(unsafe { (*msg).GetSetting }, "GetSetting")?;
+    let mut size = INITIAL_BUFFER_SIZE;
+    loop {
+        let mut buffer = vec![0u8; size];
+        unsafe {
+            get_setting(
+                name_space_c.as_ptr(),
+                name_c.as_ptr(),
+                buffer.as_mut_ptr() as *mut c_char,
+                size as c_uint,
+            );
+        }
+        // make sure we never read past the buffer, even if the host did not terminate it
+        buffer[size - 1] = 0;
+        let value = CStr::from_bytes_until_nul(&buffer).unwrap();
+        let len = value.to_bytes().len();
+        // a completely filled buffer means the value was probably truncated
+        if len < size - 1 {
+            let value = value.to_str().map_err(|_| SettingError::NotUtf8)?;
+            return Ok((!value.is_empty()).then(|| value.to_string()));
+        }
+        if size >= MAX_BUFFER_SIZE {
+            return Err(SettingError::TooLong);
+        }
+        size *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test::{wrapped_api, TestMsgPluginAPI};
+    use crate::VPXApi;
+
+    #[test]
+    fn test_get_setting() {
+        let api = wrapped_api();
+        let api: &dyn VPXApi = &*api;
+
+        TestMsgPluginAPI::set_setting("Plugin.fps", "Port", "4242");
+        TestMsgPluginAPI::set_setting("Plugin.fps", "Color", "purple");
+        let long = "x".repeat(1000);
+        TestMsgPluginAPI::set_setting("Plugin.fps", "Path", &long);
+
+        assert_eq!(api.get_setting::<u16>("Plugin.fps", "Port"), Ok(Some(4242)));
+        assert_eq!(api.get_setting::<u16>("Plugin.fps", "Missing"), Ok(None));
+        assert_eq!(
+            api.get_setting::<String>("Plugin.fps", "Path"),
+            Ok(Some(long))
+        );
+        assert!(matches!(
+            api.get_setting::<u16>("Plugin.fps", "Color"),
+            Err(SettingError::Parse { .. })
+        ));
+        assert_eq!(
+            api.get_setting::<u16>("Plugin.fps", "Po\0rt"),
+            Err(SettingError::InvalidName)
+        );
+    }
+}
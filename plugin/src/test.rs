@@ -1,18 +1,157 @@
+use crate::bindings::LoggingPluginAPI;
 use crate::bindings::MsgPluginAPI;
+use crate::bindings::ScriptablePluginAPI;
 use crate::bindings::VPXPluginAPI;
-use crate::bindings::{msgpi_msg_callback, VPXTableInfo};
+use crate::bindings::{msgpi_msg_callback, VPXTableInfo, VPXViewSetupDef, BOOL};
 use crate::bindings::{msgpi_timer_callback, VPXPluginAPI_OptionUnit};
+use crate::bindings::{DmdSrcId, GetDmdSrcMsg};
+use crate::bindings::{ScriptArrayDef, ScriptClassDef, ScriptClassMemberDef, ScriptVariant};
 use log::{info, warn};
-use std::ffi::{c_uint, CStr};
+use std::cell::{Cell, RefCell};
+use std::ffi::{c_uint, CStr, CString};
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+use crate::{ScriptType, ScriptValue, WrappedPluginApi};
 
 pub const TEST_SESSION_ID: c_uint = 123;
 
+/// Messages not known up front get an id starting from here.
+const FIRST_DYNAMIC_MSG_ID: c_uint = 100;
+
+/// Callback scheduled from a thread other than the one the test host was created on.
+struct ForeignCallback(msgpi_timer_callback, *mut std::ffi::c_void);
+
+// only used to move the callback back to the test thread that runs it
+unsafe impl Send for ForeignCallback {}
+
+static FOREIGN_QUEUE: Mutex<Vec<ForeignCallback>> = Mutex::new(Vec::new());
+
+/// Everything logged through the test LoggingPlugin, from all threads.
+static HOST_LOGS: Mutex<Vec<(c_uint, String)>> = Mutex::new(Vec::new());
+
+unsafe extern "C" fn host_log(level: c_uint, message: *const std::os::raw::c_char) {
+    let message = CStr::from_ptr(message).to_string_lossy().into_owned();
+    HOST_LOGS.lock().unwrap().push((level, message));
+}
+
+static LOGGING_API: LoggingPluginAPI = LoggingPluginAPI {
+    Log: Some(host_log),
+};
+
+unsafe extern "C" fn register_script_class(class_def: *mut ScriptClassDef) {
+    let name = CStr::from_ptr((*class_def).name.name).to_string_lossy();
+    info!("TestScriptablePluginAPI::register_script_class({name})");
+    SCRIPT_CLASSES.with_borrow_mut(|classes| classes.push(class_def));
+}
+
+unsafe extern "C" fn register_script_type_alias(
+    _name: *const std::os::raw::c_char,
+    _aliased_type: *const std::os::raw::c_char,
+) {
+}
+
+unsafe extern "C" fn register_script_array_type(array_def: *mut ScriptArrayDef) {
+    let name = CStr::from_ptr((*array_def).name.name)
+        .to_string_lossy()
+        .into_owned();
+    info!("TestScriptablePluginAPI::register_script_array_type({name})");
+    SCRIPT_ARRAY_TYPES.with_borrow_mut(|types| types.push(name));
+}
+
+/// Declared without the variadic arguments the plugin never passes, see `SCRIPTABLE_API`.
+unsafe extern "C" fn on_error(_error_type: c_uint, format: *const std::os::raw::c_char) {
+    let message = CStr::from_ptr(format).to_string_lossy().replace("%%", "%");
+    info!("TestScriptablePluginAPI::on_error({message})");
+    SCRIPT_ERRORS.with_borrow_mut(|errors| errors.push(message));
+}
+
+unsafe extern "C" fn submit_type_library() {
+    info!("TestScriptablePluginAPI::submit_type_library()");
+}
+
+unsafe extern "C" fn set_com_object_override(
+    class_name: *const std::os::raw::c_char,
+    class_def: *const ScriptClassDef,
+) {
+    let class_name = CStr::from_ptr(class_name).to_string_lossy().into_owned();
+    info!("TestScriptablePluginAPI::set_com_object_override({class_name})");
+    COM_OVERRIDES.with_borrow_mut(|overrides| {
+        overrides.retain(|(name, _)| *name != class_name);
+        if !class_def.is_null() {
+            overrides.push((class_name, class_def));
+        }
+    });
+}
+
+static SCRIPTABLE_API: ScriptablePluginAPI = ScriptablePluginAPI {
+    RegisterScriptClass: Some(register_script_class),
+    RegisterScriptTypeAlias: Some(register_script_type_alias),
+    RegisterScriptArrayType: Some(register_script_array_type),
+    SubmitTypeLibrary: Some(submit_type_library),
+    // a variadic call without variadic arguments passes the fixed ones like a plain call
+    OnError: Some(unsafe {
+        std::mem::transmute::<
+            unsafe extern "C" fn(c_uint, *const std::os::raw::c_char),
+            unsafe extern "C" fn(c_uint, *const std::os::raw::c_char, ...),
+        >(on_error)
+    }),
+    SetCOMObjectOverride: Some(set_com_object_override),
+};
+
+thread_local! {
+    /// Set on the thread that created the test host, which acts as the main thread.
+    static IS_HOST_THREAD: Cell<bool> = const { Cell::new(false) };
+    static LOGGING_ENABLED: Cell<bool> = const { Cell::new(false) };
+    static SCRIPTING_ENABLED: Cell<bool> = const { Cell::new(false) };
+    /// Sources reported for `GetDmdSrc`, as if a controller plugin was loaded.
+    static DMD_SOURCES: RefCell<Vec<DmdSrcId>> = const { RefCell::new(Vec::new()) };
+    /// Every class registered so far, the type library objects are looked up in.
+    static SCRIPT_CLASSES: RefCell<Vec<*const ScriptClassDef>> = const { RefCell::new(Vec::new()) };
+    /// Classes scripts get for `CreateObject(prog_id)`, see `enable_scripting`.
+    static COM_OVERRIDES: RefCell<Vec<(String, *const ScriptClassDef)>> =
+        const { RefCell::new(Vec::new()) };
+    /// Names of the registered array types, see `script_array_types`.
+    static SCRIPT_ARRAY_TYPES: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    /// Errors raised in scripts that were not taken yet, see `take_script_errors`.
+    static SCRIPT_ERRORS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    /// Subscriptions made through the test host, tests run on their own thread.
+    static SUBSCRIPTIONS: RefCell<Vec<(c_uint, msgpi_msg_callback, *mut std::ffi::c_void)>> =
+        const { RefCell::new(Vec::new()) };
+    /// Messages registered by the plugin that are not part of the fixed set below.
+    static DYNAMIC_MESSAGES: RefCell<Vec<(String, String)>> = const { RefCell::new(Vec::new()) };
+    /// Callbacks scheduled with `RunOnMainThread` that did not run yet.
+    static MAIN_THREAD_QUEUE: RefCell<Vec<(f64, msgpi_timer_callback, *mut std::ffi::c_void)>> =
+        const { RefCell::new(Vec::new()) };
+    /// Settings returned by `GetSetting`, as if read from VPinballX.ini.
+    static SETTINGS: RefCell<Vec<(String, String, String)>> = const { RefCell::new(Vec::new()) };
+    /// Path, width and height of the loaded table, see `set_table_info`.
+    static TABLE_INFO: RefCell<Option<(CString, f32, f32)>> = const { RefCell::new(None) };
+    /// Notifications on screen as id, text and length, see `enable_notifications`.
+    static NOTIFICATIONS: RefCell<Vec<(c_uint, String, c_uint)>> = const { RefCell::new(Vec::new()) };
+    /// Every `DisableStaticPrerendering` call, see `enable_static_prerendering`.
+    static STATIC_PRERENDERING: RefCell<Vec<bool>> = const { RefCell::new(Vec::new()) };
+    /// Camera of the running table, see `enable_view_setup`.
+    static VIEW_SETUP: Cell<Option<VPXViewSetupDef>> = const { Cell::new(None) };
+    /// What a plugin gets when it asks for the vpx api, see `TestMsgPluginAPI::init`.
+    static VPX_API: Cell<Option<VPXPluginAPI>> = const { Cell::new(None) };
+    /// Option values returned by `GetOption`, the default value is returned for others.
+    static OPTIONS: RefCell<Vec<(String, String, f32)>> = const { RefCell::new(Vec::new()) };
+}
+
 pub struct TestVPXPluginAPI;
 
 impl TestVPXPluginAPI {
     pub fn init() -> VPXPluginAPI {
         unsafe extern "C" fn get_table_info(info: *mut VPXTableInfo) {
             info!("TestVPXPluginAPI::get_table_info()");
+            TABLE_INFO.with_borrow(|table| {
+                if let Some((path, width, height)) = table {
+                    (*info).path = path.as_ptr();
+                    (*info).tableWidth = *width;
+                    (*info).tableHeight = *height;
+                }
+            });
         }
 
         unsafe extern "C" fn get_option(
@@ -31,7 +170,12 @@ impl TestVPXPluginAPI {
             let option_id_rust = CStr::from_ptr(optionId).to_str().unwrap();
             let option_name_rust = CStr::from_ptr(optionName).to_str().unwrap();
             info!("TestVPXPluginAPI::get_option({page_id_rust}, {option_id_rust}, {showMask}, {option_name_rust}, {minValue}, {maxValue}, {step}, {defaultValue}, {unit})");
-            0.0
+            OPTIONS.with_borrow(|options| {
+                options
+                    .iter()
+                    .find(|(p, o, _)| p == page_id_rust && o == option_id_rust)
+                    .map_or(defaultValue, |(_, _, value)| *value)
+            })
         }
 
         VPXPluginAPI {
@@ -46,21 +190,123 @@ impl TestVPXPluginAPI {
     }
 }
 
+impl TestVPXPluginAPI {
+    /// Provides `PushNotification` and `UpdateNotification`, which `init` leaves out.
+    pub fn enable_notifications(vpx_api: &mut VPXPluginAPI) {
+        unsafe extern "C" fn push_notification(
+            message: *const ::std::os::raw::c_char,
+            length_ms: c_uint,
+        ) -> c_uint {
+            let message = CStr::from_ptr(message).to_str().unwrap().to_string();
+            NOTIFICATIONS.with_borrow_mut(|notifications| {
+                let id = notifications.len() as c_uint + 1;
+                notifications.push((id, message, length_ms));
+                id
+            })
+        }
+
+        unsafe extern "C" fn update_notification(
+            handle: c_uint,
+            message: *const ::std::os::raw::c_char,
+            length_ms: c_uint,
+        ) {
+            let message = CStr::from_ptr(message).to_str().unwrap().to_string();
+            NOTIFICATIONS.with_borrow_mut(|notifications| {
+                if let Some(notification) = notifications.iter_mut().find(|n| n.0 == handle) {
+                    notification.1 = message;
+                    notification.2 = length_ms;
+                }
+            });
+        }
+
+        vpx_api.PushNotification = Some(push_notification);
+        vpx_api.UpdateNotification = Some(update_notification);
+    }
+
+    /// Provides `DisableStaticPrerendering`, which `init` leaves out.
+    pub fn enable_static_prerendering(vpx_api: &mut VPXPluginAPI) {
+        unsafe extern "C" fn disable_static_prerendering(disable: BOOL) {
+            STATIC_PRERENDERING.with_borrow_mut(|calls| calls.push(disable != 0));
+        }
+
+        vpx_api.DisableStaticPrerendering = Some(disable_static_prerendering);
+    }
+
+    /// Every `DisableStaticPrerendering` call on the current thread, in order.
+    pub fn static_prerendering_calls() -> Vec<bool> {
+        STATIC_PRERENDERING.with_borrow(|calls| calls.clone())
+    }
+
+    /// Provides `GetActiveViewSetup` and `SetActiveViewSetup`, which `init` leaves out.
+    ///
+    /// The setup starts out as `ViewSetup::default()`.
+    pub fn enable_view_setup(vpx_api: &mut VPXPluginAPI) {
+        unsafe extern "C" fn get_active_view_setup(view: *mut VPXViewSetupDef) {
+            let default = VPXViewSetupDef::from(&crate::ViewSetup::default());
+            *view = VIEW_SETUP.get().unwrap_or(default);
+        }
+
+        unsafe extern "C" fn set_active_view_setup(view: *mut VPXViewSetupDef) {
+            VIEW_SETUP.set(Some(*view));
+        }
+
+        vpx_api.GetActiveViewSetup = Some(get_active_view_setup);
+        vpx_api.SetActiveViewSetup = Some(set_active_view_setup);
+    }
+
+    /// Every notification pushed on the current thread as text and length, with the latest
+    /// update applied. Dismissed notifications have a length of 0.
+    pub fn notifications() -> Vec<(String, c_uint)> {
+        NOTIFICATIONS.with_borrow(|notifications| {
+            notifications
+                .iter()
+                .map(|(_, text, length)| (text.clone(), *length))
+                .collect()
+        })
+    }
+
+    /// Sets the table `GetTableInfo` reports on the current thread, it reports no table
+    /// otherwise.
+    pub fn set_table_info(path: &str, width: f32, height: f32) {
+        let path = CString::new(path).unwrap();
+        TABLE_INFO.set(Some((path, width, height)));
+    }
+
+    /// Sets a value that will be returned by `GetOption` on the current thread.
+    pub fn set_option(page_id: &str, option_id: &str, value: f32) {
+        OPTIONS.with_borrow_mut(|options| {
+            options.retain(|(p, o, _)| p != page_id || o != option_id);
+            options.push((page_id.to_string(), option_id.to_string(), value));
+        });
+    }
+}
+
 pub struct TestMsgPluginAPI;
 
 impl TestMsgPluginAPI {
+    /// Plugins asking for the vpx api get a copy of `vpx_api`.
     pub fn init(vpx_api: &VPXPluginAPI) -> MsgPluginAPI {
+        IS_HOST_THREAD.set(true);
+        VPX_API.set(Some(*vpx_api));
+
         unsafe extern "C" fn subscribe_msg(
             endpoint_id: c_uint,
             msg_id: c_uint,
-            _callback: msgpi_msg_callback,
-            _user_data: *mut std::ffi::c_void,
+            callback: msgpi_msg_callback,
+            user_data: *mut std::ffi::c_void,
         ) {
             info!("TestVPXPluginAPI::subscribe_msg({msg_id})");
+            SUBSCRIPTIONS.with_borrow_mut(|s| s.push((msg_id, callback, user_data)));
         }
 
         unsafe extern "C" fn unsubscribe_msg(msg_id: c_uint, _callback: msgpi_msg_callback) {
             info!("TestVPXPluginAPI::unsubscribe_msg({msg_id})");
+            // the plugin subscribes a single trampoline per message, so the id is enough
+            SUBSCRIPTIONS.with_borrow_mut(|s| {
+                if let Some(index) = s.iter().position(|(id, _, _)| *id == msg_id) {
+                    s.remove(index);
+                }
+            });
         }
 
         unsafe extern "C" fn get_msg_id(
@@ -69,33 +315,55 @@ impl TestMsgPluginAPI {
         ) -> c_uint {
             let str_name_space = CStr::from_ptr(name_space).to_str().unwrap();
             let str_name = CStr::from_ptr(name).to_str().unwrap();
-            let event_id: i32 = message_id_for(str_name_space, str_name);
+            let event_id = message_id_for(str_name_space, str_name);
             info!(
                 "TestVPXPluginAPI::get_msg_id(\"{str_name_space}\" ,\"{str_name}\") -> {event_id}"
             );
-            event_id as c_uint
+            event_id
         }
 
-        fn message_id_for(str_name_space: &str, str_name: &str) -> i32 {
+        fn message_id_for(str_name_space: &str, str_name: &str) -> c_uint {
             match (str_name_space, str_name) {
                 ("VPX", "OnGameStart") => 1,
                 ("VPX", "OnGameEnd") => 2,
                 ("VPX", "OnPrepareFrame") => 3,
                 ("VPX", "OnSettingsChanged") => 4,
                 ("VPX", "GetAPI") => 5,
-                _ => unimplemented!("Unknown event {str_name_space}:{str_name}"),
+                ("PinMAME", "OnGameStart") => 6,
+                ("PinMAME", "OnGameEnd") => 7,
+                _ => DYNAMIC_MESSAGES.with_borrow_mut(|messages| {
+                    let key = (str_name_space.to_string(), str_name.to_string());
+                    let index = match messages.iter().position(|m| *m == key) {
+                        Some(index) => index,
+                        None => {
+                            messages.push(key);
+                            messages.len() - 1
+                        }
+                    };
+                    FIRST_DYNAMIC_MSG_ID + index as c_uint
+                }),
             }
         }
 
-        fn message_name_for(msg_id: c_uint) -> (&'static str, &'static str) {
-            match msg_id {
+        fn message_name_for(msg_id: c_uint) -> (String, String) {
+            let (name_space, name) = match msg_id {
                 1 => ("VPX", "OnGameStart"),
                 2 => ("VPX", "OnGameEnd"),
                 3 => ("VPX", "OnPrepareFrame"),
                 4 => ("VPX", "OnSettingsChanged"),
                 5 => ("VPX", "GetAPI"),
-                _ => unimplemented!("Unknown event {msg_id}"),
-            }
+                6 => ("PinMAME", "OnGameStart"),
+                7 => ("PinMAME", "OnGameEnd"),
+                _ => {
+                    return DYNAMIC_MESSAGES.with_borrow(|messages| {
+                        messages
+                            .get((msg_id - FIRST_DYNAMIC_MSG_ID) as usize)
+                            .cloned()
+                            .unwrap_or_else(|| unimplemented!("Unknown event {msg_id}"))
+                    })
+                }
+            };
+            (name_space.to_string(), name.to_string())
         }
 
         unsafe extern "C" fn broadcast_msg(
@@ -109,11 +377,36 @@ impl TestMsgPluginAPI {
             // TODO if the vpx interface is requested we should set the pointer
             if msg_id == 5 {
                 warn!("Requesting test VPXPluginAPI pointer currently leaks memory");
-                let bx = Box::new(TestVPXPluginAPI::init());
+                let bx = Box::new(VPX_API.get().unwrap_or_else(TestVPXPluginAPI::init));
                 // TODO this leaks the memory, we should later use Box::from_raw to free the memory
                 //  or find a better way to handle this.
                 *(data as *mut *mut std::ffi::c_void) = Box::into_raw(bx) as *mut std::ffi::c_void;
             }
+            if str_name_space == crate::LPI_NAMESPACE
+                && str_name == crate::LPI_MSG_GET_API
+                && LOGGING_ENABLED.get()
+            {
+                *(data as *mut *const LoggingPluginAPI) = &LOGGING_API;
+            }
+            if str_name_space == crate::SCRIPTPI_NAMESPACE
+                && str_name == crate::SCRIPTPI_MSG_GET_API
+                && SCRIPTING_ENABLED.get()
+            {
+                *(data as *mut *const ScriptablePluginAPI) = &SCRIPTABLE_API;
+            }
+            if str_name_space == crate::CTLPI_NAMESPACE && str_name == crate::CTLPI_GETDMD_SRC_MSG {
+                // like the controllers, fill in what fits and count everything
+                let msg = &mut *(data as *mut GetDmdSrcMsg);
+                DMD_SOURCES.with_borrow(|sources| {
+                    for source in sources {
+                        if msg.count < msg.maxEntryCount {
+                            *msg.entries.add(msg.count as usize) = *source;
+                        }
+                        msg.count += 1;
+                    }
+                });
+            }
+            TestMsgPluginAPI::dispatch(msg_id, data);
         }
 
         unsafe extern "C" fn release_msg_id(msg_id: c_uint) {
@@ -126,7 +419,20 @@ impl TestMsgPluginAPI {
             valueBuf: *mut ::std::os::raw::c_char,
             valueBufSize: ::std::os::raw::c_uint,
         ) {
-            info!("TestVPXPluginAPI::get_settings() not implemented");
+            let str_name_space = CStr::from_ptr(name_space).to_str().unwrap();
+            let str_name = CStr::from_ptr(name).to_str().unwrap();
+            info!("TestVPXPluginAPI::get_settings(\"{str_name_space}\", \"{str_name}\")");
+            // like the host, missing settings are returned as an empty string
+            let value = SETTINGS.with_borrow(|settings| {
+                settings
+                    .iter()
+                    .find(|(ns, n, _)| ns == str_name_space && n == str_name)
+                    .map(|(_, _, value)| value.clone())
+                    .unwrap_or_default()
+            });
+            let len = value.len().min(valueBufSize as usize - 1);
+            std::ptr::copy_nonoverlapping(value.as_ptr(), valueBuf as *mut u8, len);
+            *valueBuf.add(len) = 0;
         }
 
         unsafe extern "C" fn run_on_main_thread(
@@ -134,7 +440,15 @@ impl TestMsgPluginAPI {
             callback: msgpi_timer_callback,
             userData: *mut ::std::os::raw::c_void,
         ) {
-            info!("TestVPXPluginAPI::run_on_main_thread() not implemented");
+            info!("TestVPXPluginAPI::run_on_main_thread({delayInS})");
+            if IS_HOST_THREAD.get() {
+                MAIN_THREAD_QUEUE.with_borrow_mut(|q| q.push((delayInS, callback, userData)));
+            } else {
+                FOREIGN_QUEUE
+                    .lock()
+                    .unwrap()
+                    .push(ForeignCallback(callback, userData));
+            }
         }
 
         MsgPluginAPI {
@@ -147,4 +461,310 @@ impl TestMsgPluginAPI {
             RunOnMainThread: Some(run_on_main_thread),
         }
     }
+
+    /// Sets a value that will be returned by `GetSetting` on the current thread.
+    pub fn set_setting(name_space: &str, name: &str, value: &str) {
+        SETTINGS.with_borrow_mut(|settings| {
+            settings.retain(|(ns, n, _)| ns != name_space || n != name);
+            settings.push((name_space.to_string(), name.to_string(), value.to_string()));
+        });
+    }
+
+    /// Answers the LoggingPlugin `GetAPI` message on the current thread, like a host with the
+    /// LoggingPlugin enabled.
+    pub fn enable_logging() {
+        LOGGING_ENABLED.set(true);
+    }
+
+    /// Answers the ScriptablePlugin `GetAPI` message on the current thread, like a host that
+    /// runs table scripts.
+    pub fn enable_scripting() {
+        SCRIPTING_ENABLED.set(true);
+    }
+
+    /// Reports a DMD for `GetDmdSrc` on the current thread.
+    pub fn add_dmd_source(id: c_uint, width: c_uint, height: c_uint, format: c_uint) {
+        DMD_SOURCES.with_borrow_mut(|sources| {
+            sources.push(DmdSrcId {
+                id,
+                width,
+                height,
+                format,
+            })
+        });
+    }
+
+    /// Creates an object like a script calling `CreateObject(prog_id)` would, `None` if no
+    /// class is registered for it or the plugin refused to create one.
+    pub fn create_script_object(prog_id: &str) -> Option<TestScriptObject> {
+        let def = COM_OVERRIDES.with_borrow(|overrides| {
+            overrides
+                .iter()
+                .find(|(name, _)| name == prog_id)
+                .map(|(_, def)| *def)
+        })?;
+        let object = unsafe { (*def).CreateObject?() };
+        (!object.is_null()).then_some(TestScriptObject { def, object })
+    }
+
+    /// Array types registered on the current thread, in order.
+    pub fn script_array_types() -> Vec<String> {
+        SCRIPT_ARRAY_TYPES.with_borrow(Clone::clone)
+    }
+
+    /// Errors raised in scripts on the current thread since the last call.
+    pub fn take_script_errors() -> Vec<String> {
+        SCRIPT_ERRORS.with_borrow_mut(std::mem::take)
+    }
+
+    /// Level and text of everything logged to the host containing `needle`, from any thread.
+    pub fn host_logs(needle: &str) -> Vec<(c_uint, String)> {
+        HOST_LOGS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, message)| message.contains(needle))
+            .cloned()
+            .collect()
+    }
+
+    /// Runs everything scheduled with `RunOnMainThread` so far, ignoring the delays.
+    ///
+    /// Callbacks scheduled while running are kept for the next call, returns how many ran.
+    pub fn run_pending() -> usize {
+        let queue = MAIN_THREAD_QUEUE.take();
+        let count = queue.len();
+        for (_, callback, user_data) in queue {
+            if let Some(callback) = callback {
+                unsafe { callback(user_data) };
+            }
+        }
+        count
+    }
+
+    /// Runs everything scheduled with `RunOnMainThread` from other threads on this thread.
+    pub fn run_pending_from_threads() -> usize {
+        let queue = std::mem::take(&mut *FOREIGN_QUEUE.lock().unwrap());
+        let count = queue.len();
+        for ForeignCallback(callback, user_data) in queue {
+            if let Some(callback) = callback {
+                unsafe { callback(user_data) };
+            }
+        }
+        count
+    }
+
+    /// Number of host subscriptions for a message id on the current thread.
+    pub fn subscriptions(msg_id: c_uint) -> usize {
+        SUBSCRIPTIONS.with_borrow(|s| s.iter().filter(|(id, _, _)| *id == msg_id).count())
+    }
+
+    /// Delivers a message without payload to all subscribers, as if sent by the host.
+    pub fn broadcast(msg_id: c_uint) {
+        unsafe { Self::dispatch(msg_id, std::ptr::null_mut()) };
+    }
+
+    /// Delivers a message with payload to all subscribers, as if sent by the host.
+    ///
+    /// # Safety
+    ///
+    /// `data` must be valid for whatever the subscribers of the message expect.
+    pub unsafe fn dispatch(msg_id: c_uint, data: *mut std::ffi::c_void) {
+        // subscribers are allowed to (un)subscribe while handling the message
+        let subscribers: Vec<_> = SUBSCRIPTIONS.with_borrow(|s| {
+            s.iter()
+                .filter(|(id, _, _)| *id == msg_id)
+                .map(|(_, cb, user_data)| (*cb, *user_data))
+                .collect()
+        });
+        for (callback, user_data) in subscribers {
+            if let Some(callback) = callback {
+                callback(msg_id, user_data, data);
+            }
+        }
+    }
+}
+
+/// A [`WrappedPluginApi`] on the test host, wired up like a loaded plugin: the vpx api is set,
+/// worker thread handles are attached and scripting is attached if it was enabled before.
+pub struct TestApi {
+    // declared first so it is dropped before the host tables it points to
+    api: Box<WrappedPluginApi>,
+    vpx_api: Box<VPXPluginAPI>,
+    _msg_api: Box<MsgPluginAPI>,
+}
+
+impl TestApi {
+    /// The vpx api table the wrapped api was created with.
+    pub fn vpx_api(&mut self) -> *mut VPXPluginAPI {
+        &mut *self.vpx_api
+    }
+}
+
+impl Deref for TestApi {
+    type Target = WrappedPluginApi;
+
+    fn deref(&self) -> &Self::Target {
+        &self.api
+    }
+}
+
+impl DerefMut for TestApi {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.api
+    }
+}
+
+/// A wrapped api on a test host with the default vpx api, see [`wrapped_api_with`].
+pub fn wrapped_api() -> TestApi {
+    wrapped_api_with(TestVPXPluginAPI::init())
+}
+
+/// A wrapped api on a test host with the given vpx api, for functions `init` leaves out.
+pub fn wrapped_api_with(vpx_api: VPXPluginAPI) -> TestApi {
+    let mut vpx_api = Box::new(vpx_api);
+    let mut msg_api = Box::new(TestMsgPluginAPI::init(&vpx_api));
+    let mut api = Box::new(WrappedPluginApi::new(TEST_SESSION_ID, &mut *msg_api));
+    api.set_vpx(&mut *vpx_api);
+    api.attach_scripting();
+    let api_ptr: *mut WrappedPluginApi = &mut *api;
+    api.handle_shared.attach(api_ptr);
+    TestApi {
+        api,
+        vpx_api,
+        _msg_api: msg_api,
+    }
+}
+
+/// An object created by [`TestMsgPluginAPI::create_script_object`], released when dropped.
+pub struct TestScriptObject {
+    def: *const ScriptClassDef,
+    object: *mut std::ffi::c_void,
+}
+
+impl TestScriptObject {
+    fn members(&self) -> &[ScriptClassMemberDef] {
+        unsafe {
+            std::slice::from_raw_parts(
+                std::ptr::addr_of!((*self.def).members) as *const ScriptClassMemberDef,
+                (*self.def).nMembers as usize,
+            )
+        }
+    }
+
+    /// Index of the member named `name` that takes `arg_count` arguments, a getter and a
+    /// setter share a name.
+    fn member_index(&self, name: &str, arg_count: usize) -> usize {
+        self.members()
+            .iter()
+            .position(|member| {
+                let member_name = unsafe { CStr::from_ptr(member.name.name) };
+                member_name.to_bytes() == name.as_bytes() && member.nArgs as usize == arg_count
+            })
+            .unwrap_or_else(|| panic!("no member {name} with {arg_count} arguments"))
+    }
+
+    /// Calls a member with arguments that are already written, returns what it wrote.
+    fn call_variants(&self, index: usize, variants: &mut [ScriptVariant]) -> ScriptVariant {
+        let member = &self.members()[index];
+        let mut ret = ScriptVariant { vUInt64: 0 };
+        unsafe {
+            member.Call.unwrap()(
+                self.object,
+                index as std::os::raw::c_int,
+                variants.as_mut_ptr(),
+                &mut ret,
+            )
+        };
+        ret
+    }
+
+    fn call_index(&self, index: usize, args: &[ScriptValue]) -> ScriptValue {
+        let mut variants: Vec<ScriptVariant> = args
+            .iter()
+            .map(|arg| {
+                let mut variant = ScriptVariant { vUInt64: 0 };
+                arg.clone().write(&mut variant);
+                variant
+            })
+            .collect();
+        let ret_type =
+            ScriptType::from_name(unsafe { CStr::from_ptr(self.members()[index].type_.name) })
+                .expect("unknown return type, use call_object for objects");
+        let mut ret = self.call_variants(index, &mut variants);
+        unsafe {
+            // like the host, release the strings and arrays once the call is done
+            for (arg, variant) in args.iter().zip(&mut variants) {
+                match arg {
+                    ScriptValue::String(_) => {
+                        variant.vString.Release.unwrap()(&mut variant.vString)
+                    }
+                    ScriptValue::Array(_) => (*variant.vArray).Release.unwrap()(variant.vArray),
+                    _ => {}
+                }
+            }
+            let value = ScriptValue::read(ret_type, &ret);
+            match ret_type {
+                ScriptType::String => ret.vString.Release.unwrap()(&mut ret.vString),
+                ScriptType::Array(_) => (*ret.vArray).Release.unwrap()(ret.vArray),
+                _ => {}
+            }
+            value
+        }
+    }
+
+    /// Calls the member named `name` that takes as many arguments as given, a getter and a
+    /// setter share a name.
+    pub fn call(&self, name: &str, args: &[ScriptValue]) -> ScriptValue {
+        self.call_index(self.member_index(name, args.len()), args)
+    }
+
+    /// Calls a member that returns an object, `None` for `Nothing`. The object is looked up
+    /// by its class name among the registered classes like the host does.
+    pub fn call_object(&self, name: &str, args: &[ScriptValue]) -> Option<TestScriptObject> {
+        assert!(
+            args.is_empty(),
+            "call_object only calls members without arguments"
+        );
+        let index = self.member_index(name, 0);
+        let ret = self.call_variants(index, &mut []);
+        let object = unsafe { ret.vObject };
+        if object.is_null() {
+            return None;
+        }
+        let type_name = unsafe { CStr::from_ptr(self.members()[index].type_.name) };
+        let def = SCRIPT_CLASSES
+            .with_borrow(|classes| {
+                classes
+                    .iter()
+                    .find(|def| unsafe { CStr::from_ptr((***def).name.name) } == type_name)
+                    .copied()
+            })
+            .unwrap_or_else(|| panic!("no registered class {type_name:?}"));
+        Some(TestScriptObject { def, object })
+    }
+
+    /// Calls a member that takes a single object, like `other.Merge(object)`.
+    pub fn call_with_object(&self, name: &str, object: &TestScriptObject) -> ScriptValue {
+        let index = self.member_index(name, 1);
+        let mut variants = [ScriptVariant {
+            vObject: object.object,
+        }];
+        let ret = self.call_variants(index, &mut variants);
+        let ret_type =
+            ScriptType::from_name(unsafe { CStr::from_ptr(self.members()[index].type_.name) })
+                .expect("unknown return type");
+        assert_ne!(
+            ret_type,
+            ScriptType::String,
+            "strings are not released here"
+        );
+        unsafe { ScriptValue::read(ret_type, &ret) }
+    }
+}
+
+impl Drop for TestScriptObject {
+    fn drop(&mut self) {
+        self.call("Release", &[]);
+    }
 }
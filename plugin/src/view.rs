@@ -0,0 +1,255 @@
+use crate::bindings::VPXViewSetupDef;
+use crate::VpxError;
+
+/// VPX units per centimetre, 50 units are 1.0625 inch.
+pub const VPU_PER_CM: f32 = 50.0 / (2.54 * 1.0625);
+
+/// How the table is projected, `viewMode` in the host api.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ViewMode {
+    #[default]
+    Legacy,
+    Camera,
+    /// Real world window, used for head tracking
+    Window,
+    /// A mode this crate does not know about yet, kept as is
+    Unknown(i32),
+}
+
+impl From<i32> for ViewMode {
+    fn from(mode: i32) -> Self {
+        match mode {
+            0 => ViewMode::Legacy,
+            1 => ViewMode::Camera,
+            2 => ViewMode::Window,
+            other => ViewMode::Unknown(other),
+        }
+    }
+}
+
+impl From<ViewMode> for i32 {
+    fn from(mode: ViewMode) -> Self {
+        match mode {
+            ViewMode::Legacy => 0,
+            ViewMode::Camera => 1,
+            ViewMode::Window => 2,
+            ViewMode::Unknown(other) => other,
+        }
+    }
+}
+
+/// The camera setup of the running table, see `VPXViewSetupDef` in VPXPlugin.h for the
+/// meaning of the fields.
+///
+/// Read it with `get_active_view_setup`, change what you need and write it back with
+/// `set_active_view_setup`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewSetup {
+    pub view_mode: ViewMode,
+    pub scene_scale_x: f32,
+    pub scene_scale_y: f32,
+    pub scene_scale_z: f32,
+    pub view_x: f32,
+    pub view_y: f32,
+    pub view_z: f32,
+    pub look_at: f32,
+    pub viewport_rotation: f32,
+    /// Field of view in degrees
+    pub fov: f32,
+    pub layback: f32,
+    pub view_h_offset: f32,
+    pub view_v_offset: f32,
+    pub window_top_z_offset: f32,
+    pub window_bottom_z_offset: f32,
+    pub screen_width: f32,
+    pub screen_height: f32,
+    pub screen_inclination: f32,
+    pub real_to_virtual_scale: f32,
+    pub interpupillary_distance: f32,
+}
+
+/// A neutral setup to start from, not the defaults VPX uses for new tables.
+impl Default for ViewSetup {
+    fn default() -> Self {
+        Self {
+            view_mode: ViewMode::Legacy,
+            scene_scale_x: 1.0,
+            scene_scale_y: 1.0,
+            scene_scale_z: 1.0,
+            view_x: 0.0,
+            view_y: 0.0,
+            view_z: 0.0,
+            look_at: 0.0,
+            viewport_rotation: 0.0,
+            fov: 45.0,
+            layback: 0.0,
+            view_h_offset: 0.0,
+            view_v_offset: 0.0,
+            window_top_z_offset: 0.0,
+            window_bottom_z_offset: 0.0,
+            screen_width: 0.0,
+            screen_height: 0.0,
+            screen_inclination: 0.0,
+            real_to_virtual_scale: 1.0,
+            interpupillary_distance: 0.0,
+        }
+    }
+}
+
+impl ViewSetup {
+    /// Rejects values the host can not render, checked before writing the setup back.
+    pub fn validate(&self) -> Result<(), VpxError> {
+        let fields = [
+            ("sceneScaleX", self.scene_scale_x),
+            ("sceneScaleY", self.scene_scale_y),
+            ("sceneScaleZ", self.scene_scale_z),
+            ("viewX", self.view_x),
+            ("viewY", self.view_y),
+            ("viewZ", self.view_z),
+            ("lookAt", self.look_at),
+            ("viewportRotation", self.viewport_rotation),
+            ("FOV", self.fov),
+            ("layback", self.layback),
+            ("viewHOfs", self.view_h_offset),
+            ("viewVOfs", self.view_v_offset),
+            ("windowTopZOfs", self.window_top_z_offset),
+            ("windowBottomZOfs", self.window_bottom_z_offset),
+            ("screenWidth", self.screen_width),
+            ("screenHeight", self.screen_height),
+            ("screenInclination", self.screen_inclination),
+            ("realToVirtualScale", self.real_to_virtual_scale),
+            ("interpupillaryDistance", self.interpupillary_distance),
+        ];
+        if let Some((name, _)) = fields.iter().find(|(_, value)| !value.is_finite()) {
+            return Err(VpxError::InvalidValue(name));
+        }
+        let positive = [
+            ("sceneScaleX", self.scene_scale_x),
+            ("sceneScaleY", self.scene_scale_y),
+            ("sceneScaleZ", self.scene_scale_z),
+            ("realToVirtualScale", self.real_to_virtual_scale),
+        ];
+        if let Some((name, _)) = positive.iter().find(|(_, value)| *value <= 0.0) {
+            return Err(VpxError::InvalidValue(name));
+        }
+        let non_negative = [
+            ("screenWidth", self.screen_width),
+            ("screenHeight", self.screen_height),
+            ("interpupillaryDistance", self.interpupillary_distance),
+        ];
+        if let Some((name, _)) = non_negative.iter().find(|(_, value)| *value < 0.0) {
+            return Err(VpxError::InvalidValue(name));
+        }
+        if !(self.fov > 0.0 && self.fov < 180.0) {
+            return Err(VpxError::InvalidValue("FOV"));
+        }
+        Ok(())
+    }
+}
+
+impl From<&VPXViewSetupDef> for ViewSetup {
+    fn from(def: &VPXViewSetupDef) -> Self {
+        Self {
+            view_mode: ViewMode::from(def.viewMode),
+            scene_scale_x: def.sceneScaleX,
+            scene_scale_y: def.sceneScaleY,
+            scene_scale_z: def.sceneScaleZ,
+            view_x: def.viewX,
+            view_y: def.viewY,
+            view_z: def.viewZ,
+            look_at: def.lookAt,
+            viewport_rotation: def.viewportRotation,
+            fov: def.FOV,
+            layback: def.layback,
+            view_h_offset: def.viewHOfs,
+            view_v_offset: def.viewVOfs,
+            window_top_z_offset: def.windowTopZOfs,
+            window_bottom_z_offset: def.windowBottomZOfs,
+            screen_width: def.screenWidth,
+            screen_height: def.screenHeight,
+            screen_inclination: def.screenInclination,
+            real_to_virtual_scale: def.realToVirtualScale,
+            interpupillary_distance: def.interpupillaryDistance,
+        }
+    }
+}
+
+impl From<&ViewSetup> for VPXViewSetupDef {
+    fn from(setup: &ViewSetup) -> Self {
+        Self {
+            viewMode: setup.view_mode.into(),
+            sceneScaleX: setup.scene_scale_x,
+            sceneScaleY: setup.scene_scale_y,
+            sceneScaleZ: setup.scene_scale_z,
+            viewX: setup.view_x,
+            viewY: setup.view_y,
+            viewZ: setup.view_z,
+            lookAt: setup.look_at,
+            viewportRotation: setup.viewport_rotation,
+            FOV: setup.fov,
+            layback: setup.layback,
+            viewHOfs: setup.view_h_offset,
+            viewVOfs: setup.view_v_offset,
+            windowTopZOfs: setup.window_top_z_offset,
+            windowBottomZOfs: setup.window_bottom_z_offset,
+            screenWidth: setup.screen_width,
+            screenHeight: setup.screen_height,
+            screenInclination: setup.screen_inclination,
+            realToVirtualScale: setup.real_to_virtual_scale,
+            interpupillaryDistance: setup.interpupillary_distance,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::GameStart;
+    use crate::test::{wrapped_api_with, TestMsgPluginAPI, TestVPXPluginAPI};
+    use crate::{Message, VPXApi};
+
+    #[test]
+    fn test_read_modify_write() {
+        let mut vpx_api = TestVPXPluginAPI::init();
+        TestVPXPluginAPI::enable_view_setup(&mut vpx_api);
+        let api = wrapped_api_with(vpx_api);
+        let api: &dyn VPXApi = &*api;
+
+        assert_eq!(
+            api.set_active_view_setup(&ViewSetup::default()),
+            Err(VpxError::NotInGame)
+        );
+        let game_start = api
+            .get_msg_id(GameStart::NAMESPACE, GameStart::NAME)
+            .unwrap();
+        TestMsgPluginAPI::broadcast(game_start.raw());
+
+        let mut setup = api.get_active_view_setup().unwrap();
+        assert_eq!(setup, ViewSetup::default());
+        setup.view_mode = ViewMode::Window;
+        setup.view_x = 12.5;
+        api.set_active_view_setup(&setup).unwrap();
+        assert_eq!(api.get_active_view_setup().unwrap(), setup);
+
+        setup.fov = f32::NAN;
+        assert_eq!(
+            api.set_active_view_setup(&setup),
+            Err(VpxError::InvalidValue("FOV"))
+        );
+        setup.fov = 45.0;
+        setup.scene_scale_y = 0.0;
+        assert_eq!(
+            api.set_active_view_setup(&setup),
+            Err(VpxError::InvalidValue("sceneScaleY"))
+        );
+        assert_eq!(api.get_active_view_setup().unwrap().view_x, 12.5);
+    }
+
+    #[test]
+    fn test_view_mode() {
+        for mode in [ViewMode::Legacy, ViewMode::Camera, ViewMode::Window] {
+            assert_eq!(ViewMode::from(i32::from(mode)), mode);
+        }
+        assert_eq!(ViewMode::from(7), ViewMode::Unknown(7));
+    }
+}
@@ -1,36 +1,56 @@
-use log::info;
-use vpinball_plugin_api::bindings::{OptionUnit, VPX_OPT_SHOW_TWEAK, VPX_OPT_SHOW_UI};
-use vpinball_plugin_api::{plugin, Plugin, VPXApi};
+use log::{info, warn};
+use vpinball_plugin_api::{
+    plugin, HostFunction, OptionChoice, Options, Plugin, PluginOptions, VPXApi,
+};
 
-struct RainbowPlugin {}
+#[derive(OptionChoice, Clone, Copy, Debug, Default, PartialEq)]
+enum Color {
+    #[default]
+    Red,
+    Blue,
+}
+
+// how should a plugin know it's id? Or maybe it shouldn't
+#[derive(PluginOptions, PartialEq)]
+#[options(page = "rainbow")]
+struct RainbowOptions {
+    #[option(name = "Use red or blue")]
+    color: Color,
+}
+
+struct RainbowPlugin {
+    options: Option<Options<RainbowOptions>>,
+}
 
 impl Plugin for RainbowPlugin {
+    const ID: &'static str = "rainbow.dmd";
+    const REQUIRED_FUNCTIONS: &'static [HostFunction] = &[HostFunction::GetOption];
+
     fn new() -> Self {
-        RainbowPlugin {}
+        RainbowPlugin { options: None }
     }
 
     fn on_load(&mut self, api: &mut dyn VPXApi) {
         info!("Rainbow plugin loading");
 
-        let red_blue = ["Red", "Blue"];
-        let page_id = "rainbow"; // how should a plugin know it's id? Or maybe it shouldn't
-        let opt = api.get_option(
-            page_id,
-            "color",
-            VPX_OPT_SHOW_UI | VPX_OPT_SHOW_TWEAK,
-            "Use red or blue",
-            0.0,
-            1.0,
-            1.0,
-            0.0,
-            OptionUnit::None,
-            &red_blue,
-        ) as i32;
-        info!("Rainbow plugin option: {}", opt);
+        match api.options::<RainbowOptions>() {
+            Ok(options) => {
+                info!("Rainbow plugin color: {:?}", options.get().color);
+                options.on_changed(|old, new| {
+                    info!(
+                        "Rainbow plugin color changed: {:?} -> {:?}",
+                        old.color, new.color
+                    );
+                });
+                self.options = Some(options);
+            }
+            Err(error) => warn!("Rainbow plugin options unavailable: {error}"),
+        }
     }
 
     fn on_unload(&mut self) {
         info!("Rainbow plugin unloading");
+        self.options = None;
     }
 }
 